@@ -0,0 +1,800 @@
+// Copyright 2022 Pranav Karawale
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::{DeserializeOwned, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use store::{
+    append_to_file_name, Entry, Settings, StoreError, StoreErrorKind,
+    StoredEntry, STORE_VERSION,
+};
+use util;
+
+/// Storage that the entries of a store are loaded from and saved to.
+pub trait StorageBackend {
+    /// Loads all the entries from the storage.
+    fn load(&mut self) -> Result<HashMap<String, Entry>, StoreError>;
+
+    /// Saves the changes to the entries with the given keys. `entries` holds
+    /// all the entries of the store, so the changed keys that are missing
+    /// from it have been deleted.
+    fn save(
+        &mut self,
+        entries: &HashMap<String, Entry>,
+        changed: &HashSet<String>,
+    ) -> Result<(), StoreError>;
+
+    /// Returns the settings of the store, as they were when the entries were
+    /// loaded.
+    fn settings(&self) -> Settings;
+
+    /// Changes the settings of the store, which are saved along with the
+    /// entries the next time they are saved.
+    fn set_settings(&mut self, settings: Settings);
+
+    /// Sets whether the storage should be written as compactly as possible,
+    /// if the backend has a choice.
+    fn set_compact(&mut self, _compact: bool) {}
+}
+
+/// Kinds of backends that a store can be kept in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackendKind {
+    /// A JSON file, which is rewritten whenever the store is saved.
+    Json,
+    /// A YAML file, which is rewritten whenever the store is saved.
+    Yaml,
+    /// An SQLite database, which is updated entry by entry.
+    Sqlite,
+}
+
+impl BackendKind {
+    /// Returns the kind of backend for the given store path, based on its
+    /// extension. Anything other than YAML or an SQLite database is kept as
+    /// JSON.
+    pub fn from_path(path: &Path) -> BackendKind {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => BackendKind::Yaml,
+            Some("db") | Some("sqlite") | Some("sqlite3") => {
+                BackendKind::Sqlite
+            }
+            _ => BackendKind::Json,
+        }
+    }
+
+    /// Opens a backend of this kind at the given path.
+    pub fn open(
+        self,
+        path: &Path,
+    ) -> Result<Box<dyn StorageBackend>, StoreError> {
+        Ok(match self {
+            BackendKind::Json => {
+                Box::new(FileBackend::new(path, FileFormat::Json))
+            }
+            BackendKind::Yaml => {
+                Box::new(FileBackend::new(path, FileFormat::Yaml))
+            }
+            BackendKind::Sqlite => Box::new(SqliteBackend::new(path)?),
+        })
+    }
+}
+
+/// Formats that the store file of a [`FileBackend`] can be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    /// JSON, pretty-printed unless compact.
+    Json,
+    /// YAML.
+    Yaml,
+}
+
+/// The contents of a store file since version 2. Version 1 store files only
+/// contain the entries.
+#[derive(Serialize)]
+struct Envelope<'a> {
+    /// The version of the store file.
+    version: u64,
+    /// The settings of the store, left out if they are the defaults.
+    #[serde(skip_serializing_if = "is_default")]
+    settings: Settings,
+    /// The entries of the store, sorted by their keys so that saving the
+    /// same entries always writes the same file.
+    entries: BTreeMap<&'a String, &'a Entry>,
+}
+
+/// The version of a store file, read before anything else so that a newer
+/// file is reported as such, whatever the shape of the rest of it.
+struct StoredVersion {
+    /// The version, if it is a number. A version 1 store file has none,
+    /// though it can have an entry called `version`.
+    version: Option<VersionField>,
+}
+
+impl<'de> Deserialize<'de> for StoredVersion {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<StoredVersion, D::Error> {
+        // Unlike a derived struct, only accept a map, and skip everything but
+        // the version without parsing it.
+        struct StoredVersionVisitor;
+
+        impl<'de> Visitor<'de> for StoredVersionVisitor {
+            type Value = StoredVersion;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<StoredVersion, A::Error> {
+                let mut version = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    if key == "version" {
+                        version = Some(map.next_value()?);
+                    } else {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+                Ok(StoredVersion { version })
+            }
+        }
+
+        deserializer.deserialize_map(StoredVersionVisitor)
+    }
+}
+
+/// The value under the `version` key of a store file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VersionField {
+    /// The version of a store file since version 2.
+    Number(u64),
+    /// An entry called `version` of a version 1 store file.
+    Entry(IgnoredAny),
+}
+
+/// The contents of a store file since version 2 as they are read, after its
+/// version was checked.
+#[derive(Deserialize)]
+struct StoredEnvelope {
+    #[serde(default)]
+    settings: Settings,
+    #[serde(default)]
+    entries: HashMap<String, StoredEntry>,
+}
+
+/// Whether the settings are the defaults, so that they can be left out of
+/// the store file.
+fn is_default(settings: &Settings) -> bool {
+    *settings == Settings::default()
+}
+
+/// Returns an error if a store file of the given version is too new to be
+/// read, since writing it again could lose what it has that this version
+/// doesn't know about.
+fn check_version(version: u64) -> Result<(), StoreError> {
+    if version > STORE_VERSION {
+        return Err(StoreError::App(StoreErrorKind::UnsupportedVersion(
+            version,
+        )));
+    }
+    Ok(())
+}
+
+/// Backend keeping the entries in a single file.
+pub struct FileBackend {
+    /// The path to the store file.
+    path: PathBuf,
+    /// The format of the store file.
+    format: FileFormat,
+    /// Whether the store file is saved as compact JSON instead of
+    /// pretty-printed JSON. YAML is never compact.
+    compact: bool,
+    /// The settings of the store.
+    settings: Settings,
+}
+
+impl FileBackend {
+    /// Creates a new backend for the file at the given path.
+    pub fn new(path: &Path, format: FileFormat) -> FileBackend {
+        FileBackend {
+            path: path.to_path_buf(),
+            format,
+            compact: false,
+            settings: Settings::default(),
+        }
+    }
+
+    /// Returns the path to the temporary file used while saving the store.
+    fn temp_path(&self) -> PathBuf {
+        append_to_file_name(&self.path, ".tmp")
+    }
+
+    /// Parses the store file as it is read, without keeping a copy of it,
+    /// while checking its encoding so that a stray byte is reported as such
+    /// instead of as a confusing parse error.
+    fn parse<T: DeserializeOwned>(&self) -> Result<T, StoreError> {
+        let file = std::fs::File::open(&self.path)?;
+        let mut reader = Utf8Reader::new(BufReader::new(file));
+        match self.format {
+            FileFormat::Json => {
+                serde_json::from_reader(&mut reader).map_err(|e| e.to_string())
+            }
+            FileFormat::Yaml => {
+                serde_yaml::from_reader(&mut reader).map_err(|e| e.to_string())
+            }
+        }
+        .map_err(|reason| match reader.invalid_at {
+            Some(offset) => StoreError::App(StoreErrorKind::NotUtf8(
+                self.path.clone(),
+                offset,
+            )),
+            None => StoreError::App(StoreErrorKind::CorruptStore(
+                self.path.clone(),
+                reason,
+            )),
+        })
+    }
+
+    /// Writes the entries to the temporary file, making sure they reach the
+    /// disk.
+    fn write_temp(
+        &self,
+        entries: &HashMap<String, Entry>,
+    ) -> Result<(), StoreError> {
+        let temp = util::open_private_file(
+            &self.temp_path(),
+            OpenOptions::new().truncate(true),
+        )?;
+        let mut writer = BufWriter::new(temp);
+        let envelope = Envelope {
+            version: STORE_VERSION,
+            settings: self.settings,
+            entries: entries.iter().collect(),
+        };
+        match self.format {
+            FileFormat::Json => if self.compact {
+                serde_json::to_writer(&mut writer, &envelope)
+            } else {
+                serde_json::to_writer_pretty(&mut writer, &envelope)
+            }
+            .map_err(|e| {
+                if e.is_io() {
+                    StoreError::Io(e.into())
+                } else {
+                    StoreError::Json(e)
+                }
+            })?,
+            FileFormat::Yaml => serde_yaml::to_writer(&mut writer, &envelope)?,
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(())
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn load(&mut self) -> Result<HashMap<String, Entry>, StoreError> {
+        // If the parent directory of the store file does not exist, create it.
+        if let Some(parent_dir) = self.path.parent() {
+            if !parent_dir.exists() {
+                util::create_private_dir_all(parent_dir)?;
+            }
+        }
+        // If the store file does not exist, create it.
+        if !self.path.exists() {
+            util::open_private_file(&self.path, &mut OpenOptions::new())?;
+        }
+
+        if std::fs::metadata(&self.path)?.len() == 0 {
+            // If the store file is empty, there is no point in going further
+            // to parse it, so return.
+            return Ok(HashMap::new());
+        }
+        // Check the version before the rest, since a newer version can store
+        // the entries in a way that this version can't parse.
+        let entries = match self.parse::<StoredVersion>()?.version {
+            Some(VersionField::Number(version)) => {
+                check_version(version)?;
+                let envelope = self.parse::<StoredEnvelope>()?;
+                self.settings = envelope.settings;
+                envelope.entries
+            }
+            _ => self.parse::<HashMap<String, StoredEntry>>()?,
+        };
+        Ok(entries.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+
+    fn save(
+        &mut self,
+        entries: &HashMap<String, Entry>,
+        _changed: &HashSet<String>,
+    ) -> Result<(), StoreError> {
+        // Write to a temporary file and then move it over the store file, so
+        // that a failure midway never leaves the store file partially written.
+        let temp_path = self.temp_path();
+        let result = self.write_temp(entries).and_then(|_| {
+            std::fs::rename(&temp_path, &self.path)?;
+            Ok(())
+        });
+        if result.is_err() {
+            // The temporary file is of no use anymore, so try to remove it.
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result?;
+        Ok(())
+    }
+
+    fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+
+    fn set_compact(&mut self, compact: bool) {
+        self.compact = compact;
+    }
+}
+
+/// Reader that passes the bytes of another reader through, failing at the
+/// first byte that isn't valid UTF-8.
+struct Utf8Reader<R> {
+    /// The reader that the bytes are read from.
+    inner: R,
+    /// How many bytes were read so far.
+    offset: usize,
+    /// The bytes of a character that was cut off at the end of the last
+    /// read, and the offset of its first byte.
+    pending: Vec<u8>,
+    pending_at: usize,
+    /// The offset of the first invalid byte, once there is one.
+    invalid_at: Option<usize>,
+}
+
+impl<R: Read> Utf8Reader<R> {
+    fn new(inner: R) -> Utf8Reader<R> {
+        Utf8Reader {
+            inner,
+            offset: 0,
+            pending: Vec::new(),
+            pending_at: 0,
+            invalid_at: None,
+        }
+    }
+
+    /// Records that the byte at the given offset is invalid.
+    fn invalid(&mut self, offset: usize) -> io::Error {
+        self.invalid_at = Some(offset);
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    }
+}
+
+impl<R: Read> Read for Utf8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !self.pending.is_empty() {
+            return Err(self.invalid(self.pending_at));
+        }
+        // Finish the character that was cut off by the last read first.
+        let mut start = 0;
+        while !self.pending.is_empty() && start < n {
+            self.pending.push(buf[start]);
+            start += 1;
+            match std::str::from_utf8(&self.pending) {
+                Ok(_) => self.pending.clear(),
+                Err(e) if e.error_len().is_some() => {
+                    return Err(self.invalid(self.pending_at));
+                }
+                Err(_) => {}
+            }
+        }
+        if let Err(e) = std::str::from_utf8(&buf[start..n]) {
+            let valid_up_to = start + e.valid_up_to();
+            if e.error_len().is_some() {
+                return Err(self.invalid(self.offset + valid_up_to));
+            }
+            self.pending.extend_from_slice(&buf[valid_up_to..n]);
+            self.pending_at = self.offset + valid_up_to;
+        }
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// Backend keeping the entries in an SQLite database. Only the changed
+/// entries are written when the store is saved, which is much faster than
+/// rewriting a JSON file for large stores.
+pub struct SqliteBackend {
+    /// The path to the database.
+    path: PathBuf,
+    /// The connection to the database.
+    connection: Connection,
+    /// The settings of the store.
+    settings: Settings,
+    /// Whether the settings changed since they were last saved.
+    settings_changed: bool,
+}
+
+impl SqliteBackend {
+    /// Opens the SQLite database at the given path, creating it if needed.
+    pub fn new(path: &Path) -> Result<SqliteBackend, StoreError> {
+        // An empty file is an empty database, so create it first to give it
+        // the same permissions as the other store files.
+        if !path.exists() {
+            util::open_private_file(path, &mut OpenOptions::new())?;
+        }
+        let connection = Connection::open(path)?;
+        // The entries are kept as JSON, so that the table doesn't need to
+        // change whenever the entries get new fields.
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS entries (
+                key TEXT PRIMARY KEY NOT NULL,
+                entry TEXT NOT NULL
+            )",
+            [],
+        )?;
+        // The settings are the only row of their table, kept as JSON too.
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS settings (settings TEXT NOT NULL)",
+            [],
+        )?;
+        // The version is kept in the user version of the database, which
+        // is 0 for the databases written before it was.
+        let version: u32 =
+            connection
+                .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        check_version(u64::from(version))?;
+        if u64::from(version) < STORE_VERSION {
+            connection.pragma_update(
+                None,
+                "user_version",
+                STORE_VERSION as u32,
+            )?;
+        }
+        Ok(SqliteBackend {
+            path: path.to_path_buf(),
+            connection,
+            settings: Settings::default(),
+            settings_changed: false,
+        })
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn load(&mut self) -> Result<HashMap<String, Entry>, StoreError> {
+        let mut statement =
+            self.connection.prepare("SELECT key, entry FROM entries")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        // Rows that can't be read are reported like a corrupt store file, so
+        // that --repair can move the database aside.
+        let corrupt = |reason: String| {
+            StoreError::App(StoreErrorKind::CorruptStore(
+                self.path.clone(),
+                reason,
+            ))
+        };
+        let mut entries = HashMap::new();
+        for row in rows {
+            let (key, entry) = row?;
+            let entry = serde_json::from_str::<StoredEntry>(&entry)
+                .map_err(|e| corrupt(format!("entry {}: {}", key, e)))?;
+            entries.insert(key, entry.into());
+        }
+        let settings = self
+            .connection
+            .query_row("SELECT settings FROM settings", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?;
+        if let Some(settings) = settings {
+            self.settings = serde_json::from_str(&settings)
+                .map_err(|e| corrupt(format!("settings: {}", e)))?;
+        }
+        Ok(entries)
+    }
+
+    fn save(
+        &mut self,
+        entries: &HashMap<String, Entry>,
+        changed: &HashSet<String>,
+    ) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction()?;
+        for key in changed {
+            match entries.get(key) {
+                Some(entry) => transaction.execute(
+                    "INSERT OR REPLACE INTO entries (key, entry) VALUES (?1, ?2)",
+                    params![key, serde_json::to_string(entry)?],
+                )?,
+                None => transaction
+                    .execute("DELETE FROM entries WHERE key = ?1", [key])?,
+            };
+        }
+        if self.settings_changed {
+            transaction.execute("DELETE FROM settings", [])?;
+            transaction.execute(
+                "INSERT INTO settings (settings) VALUES (?1)",
+                [serde_json::to_string(&self.settings)?],
+            )?;
+        }
+        transaction.commit()?;
+        self.settings_changed = false;
+        Ok(())
+    }
+
+    fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+        self.settings_changed = true;
+    }
+}
+
+// Tests for the store backends.
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+
+    fn run_test(test: fn(&Path)) {
+        // Setup
+        let store_dir = tempfile::tempdir().unwrap();
+        // Run the test
+        test(store_dir.path());
+        // Teardown
+        // ...
+    }
+
+    #[test]
+    fn test_backend_kind_from_path() {
+        let kind = |path| BackendKind::from_path(Path::new(path));
+        assert_eq!(kind("store.json"), BackendKind::Json);
+        assert_eq!(kind("store"), BackendKind::Json);
+        assert_eq!(kind("store.yml"), BackendKind::Yaml);
+        assert_eq!(kind("store.db"), BackendKind::Sqlite);
+        assert_eq!(kind("store.sqlite3"), BackendKind::Sqlite);
+    }
+
+    #[test]
+    fn test_backends() {
+        run_test(|store_dir| {
+            for (kind, file_name) in [
+                (BackendKind::Json, "store.json"),
+                (BackendKind::Yaml, "store.yaml"),
+                (BackendKind::Sqlite, "store.db"),
+            ] {
+                let path = store_dir.join(file_name);
+                let mut entries = HashMap::from([
+                    ("key1".to_string(), Entry::new("value1")),
+                    ("key2".to_string(), Entry::new("value2")),
+                ]);
+                let mut changed = entries.keys().cloned().collect();
+
+                let mut backend = kind.open(&path).unwrap();
+                assert!(backend.load().unwrap().is_empty());
+                backend.save(&entries, &changed).unwrap();
+                assert_eq!(kind.open(&path).unwrap().load().unwrap(), entries);
+
+                entries.remove("key1");
+                entries.insert("key2".to_string(), Entry::new("new value"));
+                backend.save(&entries, &changed).unwrap();
+                assert_eq!(kind.open(&path).unwrap().load().unwrap(), entries);
+
+                // Unchanged entries are left as they are.
+                changed.clear();
+                backend.save(&entries, &changed).unwrap();
+                assert_eq!(kind.open(&path).unwrap().load().unwrap(), entries);
+            }
+        });
+    }
+
+    #[test]
+    fn test_sorted_store_file() {
+        run_test(|store_dir| {
+            for (kind, file_name) in [
+                (BackendKind::Json, "store.json"),
+                (BackendKind::Yaml, "store.yaml"),
+            ] {
+                let path = store_dir.join(file_name);
+                let entries = ["key-b", "key-c", "key-a", "key-e", "key-d"]
+                    .iter()
+                    .map(|k| (k.to_string(), Entry::new("value")))
+                    .collect::<HashMap<_, _>>();
+                let changed = entries.keys().cloned().collect();
+
+                let mut backend = kind.open(&path).unwrap();
+                backend.load().unwrap();
+                backend.save(&entries, &changed).unwrap();
+                let content = std::fs::read_to_string(&path).unwrap();
+                let positions = ["key-a", "key-b", "key-c", "key-d", "key-e"]
+                    .iter()
+                    .map(|k| content.find(k).unwrap())
+                    .collect::<Vec<_>>();
+                assert!(positions.windows(2).all(|w| w[0] < w[1]));
+            }
+        });
+    }
+
+    #[test]
+    fn test_settings() {
+        run_test(|store_dir| {
+            for (kind, file_name) in [
+                (BackendKind::Json, "store.json"),
+                (BackendKind::Yaml, "store.yaml"),
+                (BackendKind::Sqlite, "store.db"),
+            ] {
+                let path = store_dir.join(file_name);
+                let entries = HashMap::new();
+                let settings = Settings {
+                    case_insensitive_keys: true,
+                };
+
+                let mut backend = kind.open(&path).unwrap();
+                backend.load().unwrap();
+                assert_eq!(backend.settings(), Settings::default());
+                backend.set_settings(settings);
+                backend.save(&entries, &HashSet::new()).unwrap();
+
+                let mut backend = kind.open(&path).unwrap();
+                backend.load().unwrap();
+                assert_eq!(backend.settings(), settings);
+            }
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        run_test(|store_dir| {
+            let mode = |path: &Path| {
+                std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+            };
+            for (kind, file_name) in [
+                (BackendKind::Json, "store.json"),
+                (BackendKind::Sqlite, "store.db"),
+            ] {
+                let path = store_dir.join("wwt").join(file_name);
+                let mut backend = kind.open(&path).unwrap();
+                backend.load().unwrap();
+                let entries =
+                    HashMap::from([("key".to_string(), Entry::new("value"))]);
+                let changed = entries.keys().cloned().collect();
+                backend.save(&entries, &changed).unwrap();
+                assert_eq!(mode(&path), 0o600);
+                assert_eq!(mode(path.parent().unwrap()), 0o700);
+            }
+        });
+    }
+
+    #[test]
+    fn test_corrupt_sqlite_row() {
+        run_test(|store_dir| {
+            let path = store_dir.join("store.db");
+            let mut backend = BackendKind::Sqlite.open(&path).unwrap();
+            Connection::open(&path)
+                .unwrap()
+                .execute(
+                    "INSERT INTO entries (key, entry) VALUES ('ls', '{')",
+                    [],
+                )
+                .unwrap();
+            let error = backend.load().err().unwrap();
+            assert!(matches!(
+                error,
+                StoreError::App(StoreErrorKind::CorruptStore(_, _))
+            ));
+            assert!(error.to_string().contains("--repair"));
+        });
+    }
+
+    #[test]
+    fn test_utf8_reader() {
+        // Read a byte at a time, so that the characters are cut off.
+        let read = |bytes: &[u8]| {
+            let mut reader = Utf8Reader::new(bytes);
+            let mut read = Vec::new();
+            let mut buf = [0; 1];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break Ok(read),
+                    Ok(_) => read.push(buf[0]),
+                    Err(_) => break Err(reader.invalid_at.unwrap()),
+                }
+            }
+        };
+        assert_eq!(read("aéb€".as_bytes()), Ok("aéb€".as_bytes().to_vec()));
+        assert_eq!(read(b"a\xc3\xa9\xffb"), Err(3));
+        assert_eq!(read(b"a\xe2\x82b"), Err(1));
+        assert_eq!(read(b"ab\xe2\x82"), Err(2));
+    }
+
+    #[test]
+    fn test_versions() {
+        run_test(|store_dir| {
+            let path = store_dir.join("store.json");
+            let entries =
+                HashMap::from([("ls".to_string(), Entry::new("list files"))]);
+
+            // Store files without a version are only the entries.
+            std::fs::write(&path, r#"{"ls": "list files"}"#).unwrap();
+            let mut backend = BackendKind::Json.open(&path).unwrap();
+            assert_eq!(backend.load().unwrap(), entries);
+            backend.save(&entries, &HashSet::new()).unwrap();
+            let content = std::fs::read_to_string(&path).unwrap();
+            let value: serde_json::Value =
+                serde_json::from_str(&content).unwrap();
+            assert_eq!(value["version"], STORE_VERSION);
+            assert_eq!(backend.load().unwrap(), entries);
+
+            std::fs::write(
+                &path,
+                format!(
+                    r#"{{"version": {}, "entries": {{}}}}"#,
+                    STORE_VERSION + 1
+                ),
+            )
+            .unwrap();
+            assert!(matches!(
+                backend.load(),
+                Err(StoreError::App(StoreErrorKind::UnsupportedVersion(_)))
+            ));
+
+            // A newer version is reported as such even if it stores the
+            // entries in a way that this version can't parse.
+            std::fs::write(
+                &path,
+                format!(
+                    r#"{{"version": {}, "entries": [{{"key": "ls"}}]}}"#,
+                    STORE_VERSION + 1
+                ),
+            )
+            .unwrap();
+            assert!(matches!(
+                backend.load(),
+                Err(StoreError::App(StoreErrorKind::UnsupportedVersion(_)))
+            ));
+
+            // A version 1 store file can have an entry called version.
+            std::fs::write(&path, r#"{"version": "print the version"}"#)
+                .unwrap();
+            assert_eq!(
+                backend.load().unwrap(),
+                HashMap::from([(
+                    "version".to_string(),
+                    Entry::new("print the version")
+                )])
+            );
+        });
+    }
+}
@@ -12,15 +12,114 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use clap::{Parser, Subcommand};
+use std::time::Duration;
+
+use clap::{AppSettings, ArgEnum, Parser, Subcommand};
+use clap_complete::Shell;
+
+use ui::Format;
+use what_was_that::store::Field;
+use what_was_that::util;
 
 /// CLI Implementation.
 #[derive(Parser)]
 #[clap(version, about)]
 pub struct Cli {
-    #[clap(env = "WWT_STORE_PATH", help_heading = "ENVIRONMENT")]
-    /// Custom path to the store file.
-    pub store_path: Option<String>,
+    #[clap(
+        short,
+        long,
+        env = "WWT_STORE_PATH",
+        global = true,
+        multiple_occurrences = true
+    )]
+    /// Custom path to the store file. If not given, WWT_STORE_PATH is used,
+    /// and then wwt/store.json in the config directory. Give it more than
+    /// once to find and list the things of several stores; changes only go
+    /// to the first one
+    pub store_path: Vec<String>,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        default_value = "default",
+        parse(try_from_str = parse_profile)
+    )]
+    /// The profile to use, each of which has its own store file in the
+    /// config directory. Ignored if a store path is given
+    pub profile: String,
+
+    #[clap(long, global = true)]
+    /// Save the store file as compact JSON instead of pretty-printed JSON.
+    pub compact: bool,
+
+    #[clap(long, visible_alias = "store-format", arg_enum, global = true)]
+    /// The backend to keep the store in, whatever the extension of the store
+    /// file. Without it, the backend is picked based on the extension (.yaml
+    /// or .yml for YAML, .db, .sqlite or .sqlite3 for SQLite), and JSON is
+    /// used for any other extension or none. CSV is not a store format, since
+    /// it can't hold the tags, notes, timestamps or settings of a store; use
+    /// `export csv` and `import` to convert to and from it
+    pub backend: Option<Backend>,
+
+    #[clap(long, global = true, default_value = "3")]
+    /// How many backups of the store file to keep. A backup is made before
+    /// things are forgotten
+    pub backups: usize,
+
+    #[clap(long, global = true, default_value = "4096")]
+    /// The longest description that can be remembered, in characters, unless
+    /// `--force` is given
+    pub max_length: usize,
+
+    #[clap(long, global = true)]
+    /// Treat names that only differ in case as the same name in this store,
+    /// from now on. The names keep the case they were first given in. Fails
+    /// if the store already has such names
+    pub case_insensitive_keys: bool,
+
+    #[clap(long, global = true, conflicts_with = "case-insensitive-keys")]
+    /// Treat names that only differ in case as different names in this
+    /// store again, from now on, undoing `--case-insensitive-keys`
+    pub case_sensitive_keys: bool,
+
+    #[clap(long, global = true)]
+    /// Show what would be changed, without saving anything
+    pub dry_run: bool,
+
+    #[clap(long, global = true, conflicts_with = "dry-run")]
+    /// Run the command as usual, including its messages and confirmations,
+    /// but don't save the store file, its backups or its undo snapshot.
+    /// Useful to try out scripts against the real store
+    pub no_save: bool,
+
+    #[clap(long, global = true)]
+    /// Print diagnostics, like the score of every thing that `find` matched,
+    /// to the standard error
+    pub debug: bool,
+
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    /// Only print the results and the errors, without any other messages
+    pub quiet: bool,
+
+    #[clap(short, long, global = true)]
+    /// Print more messages, and also show when the things were created and
+    /// last found
+    pub verbose: bool,
+
+    #[clap(long, global = true)]
+    /// Print the things found, listed or picked at random as
+    /// `name<TAB>description` lines, without colors or anything else. This
+    /// format doesn't change, so it is the one to parse in scripts.
+    /// Backslashes, tabs and line breaks are escaped as \\, \t, \n and \r.
+    /// With several stores, the store file comes first. --format and
+    /// --verbose are ignored
+    pub porcelain: bool,
+
+    #[clap(long, global = true)]
+    /// If the store file is corrupt, move it aside to store.json.bak (or
+    /// similar) and start over with an empty store
+    pub repair: bool,
 
     #[clap(subcommand)]
     /// Executed subcommand.
@@ -29,50 +128,861 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
-    #[clap(alias = "set", verbatim_doc_comment)]
+    #[clap(visible_alias = "set", verbatim_doc_comment)]
     /// Remember a thing and its description
     ///
+    /// Also available as `what-was-that set`.
+    ///
     /// After adding the description and the thing to the store successfully,
-    /// it will return a 0 status code.
+    /// it will return a 0 status code. If the thing is already in the store,
+    /// its description is replaced, unless `--no-clobber` is given. Its tags,
+    /// category and note are only replaced if they are given, and its pin
+    /// and how often it was found are kept.
     ///
     /// Examples:
     /// what-was-that remember "ls" "list files"
+    /// what-was-that remember "ls" "list files" --tag shell --tag files
+    /// echo "list files" | what-was-that remember "ls" -
+    /// what-was-that remember "backup" --from-file backup.sh
+    /// what-was-that remember --json '{"ls": "list files", "cat": "print"}'
     Remember {
+        #[clap(required_unless_present = "json")]
+        /// The name of the thing
+        name: Option<String>,
+        /// The description of the thing, read from the standard input if it
+        /// is `-` or not given
+        description: Option<String>,
+        #[clap(long, conflicts_with_all = &["description", "json"])]
+        /// Read the description from this file, keeping its lines, like for
+        /// a small script
+        from_file: Option<String>,
+        #[clap(short, long = "tag")]
+        /// Tag the thing, can be given multiple times
+        tags: Vec<String>,
+        #[clap(long)]
+        /// Put the thing in a category. Unlike the tags, a thing is in at most
+        /// one category
+        category: Option<String>,
+        #[clap(long)]
+        /// Extra context about the thing, like links or caveats. Shown by
+        /// `--verbose`, but not matched by `find`
+        note: Option<String>,
+        #[clap(long, conflicts_with_all = &["name", "description", "note"])]
+        /// Remember all the things in this JSON object, which maps their names
+        /// to their descriptions, at once. If any of them can't be
+        /// remembered, none of them are
+        json: Option<String>,
+        #[clap(long)]
+        /// Fail instead of replacing the thing, if it is already in the store
+        no_clobber: bool,
+        #[clap(long)]
+        /// Remember the description even if it is longer than --max-length
+        force: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Change the description of a thing
+    ///
+    /// Unlike `what-was-that remember`, this fails if the thing is not
+    /// already in the store.
+    ///
+    /// Examples:
+    /// what-was-that edit "ls" "list files in the current directory"
+    Edit {
         /// The name of the thing
         name: String,
-        /// The description of the thing
+        /// The new description of the thing
         description: String,
+        #[clap(long)]
+        /// Change the description even if it is longer than --max-length
+        force: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Change the name of a thing
+    ///
+    /// The description of the thing is kept as it is. If there is already a
+    /// thing with the new name, this fails unless `--force` is given.
+    ///
+    /// Examples:
+    /// what-was-that rename "sl" "ls"
+    Rename {
+        /// The current name of the thing
+        old_name: String,
+        /// The new name of the thing
+        new_name: String,
+        #[clap(short, long)]
+        /// Overwrite the thing with the new name, if it exists
+        force: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Remember a thing as an alias of another thing
+    ///
+    /// The alias has the same description as the other thing, and keeps it
+    /// when the description changes, whether it is changed through the alias
+    /// or the other thing. If the other thing is forgotten, the alias becomes
+    /// a thing on its own.
+    ///
+    /// Examples:
+    /// what-was-that alias "dir" "ls"
+    Alias {
+        /// The name of the alias
+        name: String,
+        /// The name of the thing to make it an alias of
+        target: String,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Pin a thing, so that it is found before the things that aren't pinned
+    ///
+    /// Pinned things are marked with a `*` in the verbose output.
+    ///
+    /// Examples:
+    /// what-was-that pin "ls"
+    Pin {
+        /// The name of the thing
+        name: String,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Unpin a thing, so that it is found by how well it matches again
+    ///
+    /// Examples:
+    /// what-was-that unpin "ls"
+    Unpin {
+        /// The name of the thing
+        name: String,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Mark a thing as still relevant
+    ///
+    /// The thing is recorded as last found now, as if it was found, without
+    /// having to search for it.
+    ///
+    /// Examples:
+    /// what-was-that touch "ls"
+    Touch {
+        /// The name of the thing
+        name: String,
     },
 
-    #[clap(alias = "get", verbatim_doc_comment)]
+    #[clap(visible_alias = "get", verbatim_doc_comment)]
     /// Find the thing using a description
     ///
+    /// Also available as `what-was-that get`.
+    ///
+    /// Both the names and the descriptions of the things are matched, unless
+    /// `--keys-only` or `--values-only` is given. An empty description
+    /// matches all the things. If nothing matches, it will return a 2
+    /// status code, or a 4 status code if the store is empty, except with
+    /// `--count`, which always returns 0 so that scripts can check the number
+    /// instead.
+    ///
+    /// With `--run`, the name of the match is run as a shell command. This
+    /// runs whatever is in the store with your permissions, so only use it
+    /// with a store that nobody else can write to, and read the command
+    /// before you confirm it.
+    ///
     /// Examples:
     /// 1. With a single entry containing the term:
-    /// $ what-was-that find "list files"
-    /// ls -> list files
+    ///    $ what-was-that find "list files"
+    ///    ls -> list files
     ///
     /// 2. With multiple entries containing the term:
-    /// $ what-was-that find "list files"
-    /// ls -> list files
-    /// ls -l -> list files with longer format
+    ///    $ what-was-that find "list files"
+    ///    ls -> list files
+    ///    ls -l -> list files with longer format
+    ///
+    /// 3. With the entries tagged "shell":
+    ///    $ what-was-that find "list files" --tag shell
+    ///    ls -> list files
+    ///
+    /// 4. With only the best match, e.g. for `cd $(what-was-that find ...)`:
+    ///    $ what-was-that find "list files" --first
+    ///    ls
+    ///
+    /// 5. With only the number of matches:
+    ///    $ what-was-that find "list files" --count
+    ///    2
+    ///
+    /// 6. Running the best match, after confirming it:
+    ///    $ what-was-that find "list files" --first --run
+    ///    Run 'ls'? [y/N]
     Find {
         /// Expected description of the thing
         description: String,
+        #[clap(
+            short = 'n',
+            long,
+            env = "WWT_FIND_LIMIT",
+            parse(try_from_str = parse_limit)
+        )]
+        /// Show only the given number of best matches. If not given,
+        /// WWT_FIND_LIMIT is used, and then all the matches are shown
+        limit: Option<usize>,
+        #[clap(short, long)]
+        /// Only find the things with the given tag
+        tag: Option<String>,
+        #[clap(long)]
+        /// Only find the things in the given category. Things without a
+        /// category are left out
+        category: Option<String>,
+        #[clap(long, conflicts_with_all = &["regex", "substring", "words"])]
+        /// Match the description fuzzily (default)
+        fuzzy: bool,
+        #[clap(long, conflicts_with_all = &["regex", "substring"])]
+        /// Match each word of the description fuzzily on its own, so that
+        /// every word has to match. The matches are ranked by the sum of the
+        /// scores of the words
+        words: bool,
+        #[clap(long, conflicts_with = "substring")]
+        /// Treat the description as a regex instead of matching it fuzzily.
+        /// The matches are sorted by their names
+        regex: bool,
+        #[clap(long)]
+        /// Only match the things whose descriptions contain this text,
+        /// ignoring the case, before ranking them by the description
+        contains: Option<String>,
+        #[clap(long)]
+        /// Match the things containing the description, ignoring the case
+        /// unless asked otherwise. The matches are sorted by their names
+        substring: bool,
+        #[clap(long, conflicts_with_all = &["smart-case", "ignore-case"])]
+        /// Tell uppercase and lowercase letters apart
+        case_sensitive: bool,
+        #[clap(long, conflicts_with = "ignore-case")]
+        /// Tell uppercase and lowercase letters apart only if the description
+        /// has uppercase letters in it
+        smart_case: bool,
+        #[clap(long)]
+        /// Don't tell uppercase and lowercase letters apart. This is the
+        /// default, unless case_mode is set in the config file
+        ignore_case: bool,
+        #[clap(long)]
+        /// Only show the matches scoring at least this much. Each matched
+        /// character scores roughly 16 to 24, more for consecutive characters
+        /// and word starts, so an exact match of "list files" scores about 215
+        min_score: Option<i64>,
+        #[clap(long)]
+        /// Show the things found most often first, instead of the best
+        /// matches
+        by_frequency: bool,
+        #[clap(long)]
+        /// Show the things that the description doesn't match instead, like
+        /// `grep -v`, sorted by their names
+        invert: bool,
+        #[clap(long, conflicts_with_all = &["values-only", "both"])]
+        /// Only match the names of the things
+        keys_only: bool,
+        #[clap(long, conflicts_with = "both")]
+        /// Only match the descriptions of the things
+        values_only: bool,
+        #[clap(long)]
+        /// Match both the names and the descriptions of the things (default)
+        both: bool,
+        #[clap(
+            long,
+            value_delimiter = ',',
+            parse(try_from_str = parse_field),
+            conflicts_with_all = &["keys-only", "values-only", "both"]
+        )]
+        /// Only match the given comma-separated fields: key, description,
+        /// note or tags. Without this, the names and the descriptions are
+        /// matched
+        fields: Vec<Field>,
+        #[clap(
+            long,
+            conflicts_with_all = &["keys-only", "values-only", "both", "fields"]
+        )]
+        /// Match the names, the descriptions and the tags of the things as
+        /// one text, so that a query with words from several of them gets a
+        /// single score. Without this, the best of their separate scores is
+        /// used
+        combined: bool,
+        #[clap(long)]
+        /// Don't highlight the matched characters of the descriptions. Setting
+        /// the NO_COLOR environment variable does the same
+        no_color: bool,
+        #[clap(long)]
+        /// Print the matches as a JSON array
+        json: bool,
+        #[clap(long, alias = "one", conflicts_with = "json")]
+        /// Only print the name of the best match. Matches with the same score
+        /// are ordered by their names, so the result is always the same
+        first: bool,
+        #[clap(long, conflicts_with_all = &["json", "first", "copy", "output", "format"])]
+        /// Only print the number of matches. Returns a 0 status code even if
+        /// nothing matches
+        count: bool,
+        #[clap(long)]
+        /// Copy the name of the best match to the clipboard
+        copy: bool,
+        #[clap(short, long)]
+        /// The file to write the matches to, instead of the standard output
+        output: Option<String>,
+        #[clap(
+            long,
+            parse(try_from_str = Format::parse),
+            conflicts_with_all = &["json", "first"]
+        )]
+        /// How to print each match: default, keys, values, tsv, or a template
+        /// with the {key}, {value} and {score} placeholders, like
+        /// "{value} ({key})". If not given, the format in the config file is
+        /// used, and then the default one
+        format: Option<Format>,
+        #[clap(long, conflicts_with_all = &["json", "first", "count"])]
+        /// Print the matches under a line for each of their tags, with the
+        /// matches indented beneath. Matches with several tags are under each
+        /// of them, and the untagged ones are under "(untagged)"
+        group_by_tag: bool,
+        #[clap(long)]
+        /// Explain why each match ranked where it did, with its score, the
+        /// field that matched and the matched characters in [brackets], on the
+        /// standard error
+        explain: bool,
+        #[clap(long, conflicts_with_all = &["json", "count", "output"])]
+        /// Run the name of the match as a command with $SHELL -c, after asking
+        /// for confirmation. DANGER: whatever is stored is run with your
+        /// permissions. Nothing is run if several things match, unless
+        /// --first is given to pick the best one
+        run: bool,
+        #[clap(short, long, requires = "run")]
+        /// Run the match without asking for confirmation
+        yes: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Show the description of a thing
+    ///
+    /// The thing is looked up by its exact name, ignoring the case if there
+    /// is no entry with the same case. Use `what-was-that find` instead to
+    /// search by the description.
+    ///
+    /// Examples:
+    /// $ what-was-that describe "ls"
+    /// list files
+    Describe {
+        /// The thing
+        name: String,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// List all the things in the store
+    ///
+    /// The entries are sorted by their name, unless specified otherwise
+    /// using `--sort`.
+    ///
+    /// Examples:
+    /// what-was-that list
+    /// what-was-that list --sort description
+    /// what-was-that list --namespace git
+    List {
+        #[clap(long)]
+        /// Only list the things in the given namespace, i.e. those with names
+        /// like `<namespace>/...`
+        namespace: Option<String>,
+        #[clap(long, arg_enum, default_value = "name")]
+        /// What to sort the entries by: the name (or key), the description
+        /// (or value), the length of the description, or when they were last
+        /// found (recent). Ties are sorted by name
+        sort: SortBy,
+        #[clap(long)]
+        /// Reverse the order of the entries
+        reverse: bool,
+        #[clap(long, parse(try_from_str = parse_since))]
+        /// Only list the things created since the given date (2022-01-05), or
+        /// for the given time, in days (7d), weeks (2w) or months of 30 days
+        /// (1m)
+        since: Option<u64>,
+        #[clap(long, parse(try_from_str = parse_until))]
+        /// Only list the things created until the given date, including that
+        /// day, or until the given time ago, like 7d
+        until: Option<u64>,
+        #[clap(long)]
+        /// Print the things as a JSON array
+        json: bool,
+        #[clap(short, long)]
+        /// The file to write the things to, instead of the standard output
+        output: Option<String>,
+        #[clap(
+            long,
+            parse(try_from_str = Format::parse),
+            conflicts_with = "json"
+        )]
+        /// How to print each thing: default, keys, values, tsv, or a template
+        /// with the {key} and {value} placeholders, like "{value} ({key})". If
+        /// not given, the format in the config file is used, and then the
+        /// default one
+        format: Option<Format>,
+        #[clap(long, conflicts_with = "json")]
+        /// Print the things under a line for each of their tags, with the
+        /// things indented beneath. Things with several tags are under each of
+        /// them, and the untagged ones are under "(untagged)"
+        group_by_tag: bool,
     },
 
-    #[clap(alias = "delete", verbatim_doc_comment)]
+    #[clap(verbatim_doc_comment)]
+    /// List the namespaces of the things
+    ///
+    /// The namespace of a thing is the part of its name before the first
+    /// `/`, like `git` for `git/rebase`.
+    ///
+    /// Examples:
+    /// $ what-was-that namespaces
+    /// docker
+    /// git
+    Namespaces,
+
+    #[clap(verbatim_doc_comment)]
+    /// List the categories of the things, with how many things are in each
+    ///
+    /// Things are put in a category with `what-was-that remember --category`.
+    ///
+    /// Examples:
+    /// $ what-was-that categories
+    /// git: 4
+    /// shell: 2
+    Categories,
+
+    #[clap(verbatim_doc_comment)]
+    /// Count the things in the store
+    ///
+    /// Examples:
+    /// $ what-was-that count
+    /// 3
+    ///
+    /// $ what-was-that count --tags
+    /// 3
+    /// files: 1
+    /// shell: 2
+    Count {
+        #[clap(long)]
+        /// Also count the things with each tag
+        tags: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Find the things with the same description
+    ///
+    /// The things are only reported, unless `--merge` is given, in which
+    /// case only the first name in each group is kept.
+    ///
+    /// Examples:
+    /// $ what-was-that dedupe
+    /// list files: dir, ls
+    ///
+    /// $ what-was-that dedupe --merge
+    /// list files: dir, ls
+    /// Forgetting ls (same as dir)
+    /// 1 forgotten.
+    Dedupe {
+        #[clap(short, long)]
+        /// Ignore the case of the descriptions
+        ignore_case: bool,
+        #[clap(long)]
+        /// Forget all but the first thing of each group
+        merge: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Show random things from the store, to rediscover them
+    ///
+    /// Examples:
+    /// $ what-was-that random
+    /// ls -> list files
+    ///
+    /// $ what-was-that random --count 2
+    /// cat -> print files
+    /// ls -> list files
+    Random {
+        #[clap(short = 'n', long, default_value = "1", parse(try_from_str = parse_limit))]
+        /// How many things to show. All of them are shown if there are fewer
+        count: usize,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Show a summary of the things in the store
+    ///
+    /// Examples:
+    /// $ what-was-that stats
+    /// Things: 3
+    /// Average description length: 14.3
+    /// Longest description: ls -l (29 characters)
+    /// Oldest thing: ls (2022-01-05 13:45:00 UTC)
+    /// Newest thing: cat (2022-01-07 09:12:30 UTC)
+    /// Tags:
+    ///     shell: 2
+    Stats,
+
+    #[clap(verbatim_doc_comment)]
+    /// Export the things in the store
+    ///
+    /// The things are exported with their names and descriptions, either as
+    /// CSV with a `key,description` header or as a JSON object. Only some of
+    /// them are exported with `--keys` or `--pattern`, and with `--remove`,
+    /// they are also forgotten once the file is written, moving them to it.
+    ///
+    /// Examples:
+    /// what-was-that export csv
+    /// what-was-that export json --output things.json
+    /// what-was-that export json --clipboard
+    /// what-was-that export json --pattern "work/*" -o work.json --remove
+    Export {
+        #[clap(arg_enum)]
+        /// The format to export the things in
+        format: ExportFormat,
+        #[clap(long, value_delimiter = ',')]
+        /// Only export the things with these names, separated by commas
+        keys: Vec<String>,
+        #[clap(long, conflicts_with = "keys")]
+        /// Only export the things with names matching this glob, where `*`
+        /// matches any text and `?` any character
+        pattern: Option<String>,
+        #[clap(long, requires = "pattern")]
+        /// Match the names with the pattern as a regex instead of a glob
+        regex: bool,
+        #[clap(long, requires = "output")]
+        /// Forget the exported things after writing them to the file
+        remove: bool,
+        #[clap(short, long)]
+        /// The file to write to, instead of the standard output
+        output: Option<String>,
+        #[clap(long, conflicts_with = "output")]
+        /// Copy the exported things to the clipboard, instead of writing them
+        /// to the standard output
+        clipboard: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Import things into the store
+    ///
+    /// The things are read from a CSV file with a `key,description` header
+    /// if the file name ends with `.csv`, or from a JSON object otherwise, as
+    /// written by `what-was-that export`. Things that are already in the
+    /// store are skipped, unless `--overwrite` is given. Things imported from
+    /// the clipboard are read as JSON if they start with `{`, and as CSV
+    /// otherwise.
+    ///
+    /// Examples:
+    /// $ what-was-that import things.csv
+    /// 4 added, 2 skipped.
+    /// $ what-was-that import --clipboard
+    /// 6 added.
+    /// $ what-was-that import things.json --ignore-case
+    /// 3 added, 2 skipped, 1 matched in another case.
+    Import {
+        #[clap(required_unless_present = "clipboard")]
+        /// The file to import the things from
+        path: Option<String>,
+        #[clap(long, conflicts_with = "path")]
+        /// Import the things from the clipboard instead of a file
+        clipboard: bool,
+        #[clap(long)]
+        /// Replace the descriptions of the things already in the store
+        overwrite: bool,
+        #[clap(long)]
+        /// Treat names that only differ in case as the same thing, so that
+        /// importing "Git" doesn't add a second thing next to "git"
+        ignore_case: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Remember and forget many things at once
+    ///
+    /// The operations are read one per line, as `set <key> <description>` or
+    /// `forget <key>`, with quotes around the keys that have spaces in them.
+    /// Blank lines and lines starting with `#` are skipped. All the
+    /// operations are applied before the store is saved, once. If any of
+    /// them fails, the others are still applied, and it returns a 1 status
+    /// code.
+    ///
+    /// Examples:
+    /// $ printf 'set "ls -l" list files\nforget cat\n' | what-was-that batch
+    /// line 1: remembered 'ls -l'
+    /// line 2: forgot 'cat'
+    Batch {
+        /// The file to read the operations from, read from the standard input
+        /// if it is `-` or not given
+        path: Option<String>,
+    },
+
+    #[clap(setting = AppSettings::Hidden, verbatim_doc_comment)]
+    /// Generate the completion script for a shell
+    ///
+    /// The script is written to the standard output, so that it can be put
+    /// where the shell looks for completion scripts.
+    ///
+    /// Examples:
+    /// what-was-that completions bash > /etc/bash_completion.d/what-was-that
+    Completions {
+        #[clap(arg_enum)]
+        /// The shell to generate the completion script for
+        shell: Shell,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Restore a backup of the store
+    ///
+    /// A backup of the store is made before things are forgotten, numbered
+    /// from 1 for the latest one. The backup is swapped with the current
+    /// store, so restoring the same backup again undoes the restore.
+    ///
+    /// Examples:
+    /// what-was-that restore
+    /// what-was-that restore 2
+    Restore {
+        #[clap(default_value = "1")]
+        /// The number of the backup
+        number: usize,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Undo the last change to the store
+    ///
+    /// Only the most recent change can be undone, and only once. Finding
+    /// things doesn't count as a change.
+    ///
+    /// Examples:
+    /// what-was-that undo
+    Undo,
+
+    #[clap(subcommand)]
+    /// Manage the profiles
+    Profiles(ProfilesCommand),
+
+    #[clap(visible_alias = "open", verbatim_doc_comment)]
+    /// Open the store file in your editor
+    ///
+    /// Also available as `what-was-that open`.
+    ///
+    /// The editor is the one in the VISUAL environment variable, then the one
+    /// in EDITOR, and then notepad on Windows or vi elsewhere. When the editor
+    /// exits, the store file is loaded again to check it. If it can't be
+    /// loaded, you are asked whether to open the editor again to fix it.
+    /// SQLite stores can't be edited this way.
+    ///
+    /// Examples:
+    /// what-was-that edit-store
+    /// EDITOR="code --wait" what-was-that edit-store
+    EditStore,
+
+    #[clap(verbatim_doc_comment)]
+    /// Check that the store can be used
+    ///
+    /// The store path is worked out, and the store file is checked to be
+    /// writable, only accessible by you and possible to load. The result of
+    /// every check is printed, and the exit code is 1 if any of them failed.
+    /// The store file isn't created or changed.
+    ///
+    /// Examples:
+    /// what-was-that doctor
+    /// what-was-that doctor --profile work
+    Doctor,
+
+    #[clap(visible_alias = "delete", verbatim_doc_comment)]
     /// Forget a thing from the store
     ///
+    /// Also available as `what-was-that delete`.
+    ///
     /// After removing the thing from the store successfully,
     /// it will return a 0 status code. Note that you will have to enter the
     /// exact thing to forget it. If you can't remember the thing itself,
     /// use `what-was-that find` to get the thing, and then run this.
     ///
+    /// You will be asked to confirm before the thing is forgotten, unless
+    /// `--yes` is given. If there is no terminal to ask on, it will refuse
+    /// to forget the thing and return a 3 status code.
+    ///
+    /// With `--pattern`, all the things with names matching the pattern are
+    /// forgotten, after listing them to confirm.
+    ///
     /// Examples:
     /// what-was-that forget "ls"
+    /// what-was-that forget "ls" --yes
+    /// what-was-that forget --pattern "docker *"
+    /// what-was-that forget --pattern "^git (log|show)" --regex
     Forget {
+        #[clap(required_unless_present = "pattern")]
         /// The thing
-        name: String,
+        name: Option<String>,
+        #[clap(long, conflicts_with = "name")]
+        /// Forget all the things with names matching this glob, where `*`
+        /// matches any text and `?` any character
+        pattern: Option<String>,
+        #[clap(long, requires = "pattern")]
+        /// Match the names with the pattern as a regex instead of a glob
+        regex: bool,
+        #[clap(short, long)]
+        /// Forget the thing without asking for confirmation
+        yes: bool,
     },
+
+    #[clap(verbatim_doc_comment)]
+    /// Forget the things that haven't been found in a long time
+    ///
+    /// Things that were never found, or last found before the timestamps
+    /// were recorded, are kept unless `--include-unknown` is given. Pass
+    /// `--dry-run` to see what would be forgotten.
+    ///
+    /// Examples:
+    /// what-was-that prune --older-than 90d
+    /// what-was-that prune --older-than 6m --dry-run
+    Prune {
+        #[clap(long, parse(try_from_str = parse_duration))]
+        /// How long ago the things must have been last found, in days (90d),
+        /// weeks (12w) or months of 30 days (6m)
+        older_than: Duration,
+        #[clap(long)]
+        /// Also forget the things that were never found
+        include_unknown: bool,
+    },
+
+    #[clap(verbatim_doc_comment)]
+    /// Forget all the things in the store
+    ///
+    /// You will be asked to confirm before the things are forgotten, unless
+    /// `--yes` is given. If there is no terminal to ask on, it will refuse
+    /// to forget the things and return a 3 status code.
+    ///
+    /// Examples:
+    /// $ what-was-that clear --yes
+    /// 3 forgotten.
+    Clear {
+        #[clap(short, long)]
+        /// Forget the things without asking for confirmation
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfilesCommand {
+    #[clap(verbatim_doc_comment)]
+    /// List the profiles that have a store file
+    ///
+    /// Examples:
+    /// $ what-was-that profiles list
+    /// default
+    /// work
+    List,
+}
+
+/// Fields that the entries can be sorted by when listing them.
+#[derive(ArgEnum, Clone, Copy)]
+pub enum SortBy {
+    /// Sort by the name of the thing.
+    #[clap(alias = "key")]
+    Name,
+    /// Sort by the description of the thing.
+    #[clap(alias = "value")]
+    Description,
+    /// Sort by the length of the description, shortest first.
+    Length,
+    /// Sort by when the thing was last found, most recent first. Things that
+    /// were never found come last.
+    Recent,
+}
+
+/// Backends that the store can be kept in.
+#[derive(ArgEnum, Clone, Copy)]
+pub enum Backend {
+    Json,
+    Yaml,
+    Sqlite,
+}
+
+/// Formats that the store can be exported in.
+#[derive(ArgEnum, Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Parses the name of a profile, which is used as the name of its store
+/// file and so can't be a path. `store` is reserved, since `store.json` is
+/// the store file of the default profile.
+fn parse_profile(profile: &str) -> Result<String, String> {
+    if profile.is_empty() || profile.contains(&['/', '\\', '.'][..]) {
+        Err("the profile name can't be empty or contain / \\ or .".to_string())
+    } else if profile == "store" {
+        Err(format!(
+            "the profile name \"store\" is reserved for the store file of the \
+            {} profile",
+            util::DEFAULT_PROFILE
+        ))
+    } else {
+        Ok(profile.to_string())
+    }
+}
+
+/// Parses the name of a field that `find` can match.
+fn parse_field(field: &str) -> Result<Field, String> {
+    match field {
+        "key" => Ok(Field::Key),
+        "description" => Ok(Field::Description),
+        "note" => Ok(Field::Note),
+        "tags" => Ok(Field::Tags),
+        _ => Err("expected key, description, note or tags".to_string()),
+    }
+}
+
+/// Parses a duration given as a number of days, weeks or months, like `90d`,
+/// `12w` or `6m`. A month is counted as 30 days.
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let split = duration.char_indices().last().map_or(0, |(i, _)| i);
+    let (number, unit) = duration.split_at(split);
+    let days = match unit {
+        "d" => 1,
+        "w" => 7,
+        "m" => 30,
+        _ => {
+            return Err(
+                "the duration must end with d, w or m, like 90d".to_string()
+            )
+        }
+    };
+    let number = number.parse::<u64>().map_err(|e| e.to_string())?;
+    number
+        .checked_mul(days * 24 * 60 * 60)
+        .map(Duration::from_secs)
+        .ok_or_else(|| "duration is too large".to_string())
+}
+
+/// Parses the start of a time range, which is either a date or how long
+/// ago it is, into seconds since the Unix epoch.
+fn parse_since(since: &str) -> Result<u64, String> {
+    match util::parse_date(since) {
+        Some(timestamp) => Ok(timestamp),
+        None => parse_time_ago(since),
+    }
+}
+
+/// Parses the end of a time range like [`parse_since`], except that a date
+/// includes the whole day.
+fn parse_until(until: &str) -> Result<u64, String> {
+    match util::parse_date(until) {
+        Some(timestamp) => Ok(timestamp + 24 * 60 * 60),
+        None => parse_time_ago(until),
+    }
+}
+
+/// Parses how long ago something is, into seconds since the Unix epoch.
+fn parse_time_ago(ago: &str) -> Result<u64, String> {
+    match parse_duration(ago) {
+        Ok(duration) => Ok(util::now().saturating_sub(duration.as_secs())),
+        Err(_) => {
+            Err("expected a date like 2022-01-05 or a duration like 7d"
+                .to_string())
+        }
+    }
+}
+
+/// Parses the limit of results, which must be a positive integer.
+fn parse_limit(limit: &str) -> Result<usize, String> {
+    match limit.parse::<usize>() {
+        Ok(0) => Err("the limit must be greater than 0".to_string()),
+        Ok(limit) => Ok(limit),
+        Err(e) => Err(e.to_string()),
+    }
 }
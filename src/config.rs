@@ -0,0 +1,173 @@
+// Copyright 2022 Pranav Karawale
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use ui::Format;
+use what_was_that::store::{CaseMatching, StoreError};
+use what_was_that::util;
+
+/// Errors that can occur while loading the config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file at the given path could not be read.
+    Io(PathBuf, std::io::Error),
+    /// The config file at the given path could not be parsed, for the given
+    /// reason.
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(
+                f,
+                "The config file at {} could not be read: {}",
+                path.display(),
+                e
+            ),
+            ConfigError::Invalid(path, reason) => write!(
+                f,
+                "The config file at {} is invalid: {}",
+                path.display(),
+                reason
+            ),
+        }
+    }
+}
+
+/// Preferences read from `config.toml` in the config directory, which are
+/// used when the flags for them aren't given.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// How many of the best matches `find` shows, like `--limit`.
+    pub default_limit: Option<usize>,
+    /// Whether the output is colored on a terminal. `false` is the same as
+    /// `--no-color`.
+    pub color: Option<bool>,
+    /// Whether `find` tells uppercase and lowercase letters apart, like
+    /// `--case-sensitive` and `--smart-case`.
+    pub case_mode: Option<CaseMatching>,
+    /// How `find` and `list` print each thing, like `--format`.
+    pub format: Option<Format>,
+}
+
+/// The config file as it is written, before the format is parsed.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    default_limit: Option<usize>,
+    color: Option<bool>,
+    case_mode: Option<CaseMode>,
+    format: Option<String>,
+}
+
+/// The values of `case_mode` in the config file.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CaseMode {
+    Ignore,
+    Respect,
+    Smart,
+}
+
+impl Config {
+    /// Loads the config file from the config directory. A missing config file,
+    /// or a missing config directory, means that nothing is configured.
+    pub fn load() -> Result<Config, ConfigError> {
+        match config_path() {
+            Ok(path) if path.exists() => {
+                let config = std::fs::read_to_string(&path)
+                    .map_err(|e| ConfigError::Io(path.clone(), e))?;
+                Config::parse(&config, &path)
+            }
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// Parses the contents of the config file at the given path.
+    pub fn parse(config: &str, path: &Path) -> Result<Config, ConfigError> {
+        let invalid =
+            |reason: String| ConfigError::Invalid(path.to_path_buf(), reason);
+        let file: ConfigFile =
+            toml::from_str(config).map_err(|e| invalid(e.to_string()))?;
+        if file.default_limit == Some(0) {
+            return Err(invalid(
+                "default_limit must be greater than 0".to_string(),
+            ));
+        }
+        let format = match file.format {
+            Some(format) => Some(
+                Format::parse(&format)
+                    .map_err(|e| invalid(format!("format: {}", e)))?,
+            ),
+            None => None,
+        };
+        Ok(Config {
+            default_limit: file.default_limit,
+            color: file.color,
+            case_mode: file.case_mode.map(|mode| match mode {
+                CaseMode::Ignore => CaseMatching::Ignore,
+                CaseMode::Respect => CaseMatching::Respect,
+                CaseMode::Smart => CaseMatching::Smart,
+            }),
+            format,
+        })
+    }
+}
+
+/// Returns the path to the config file, next to the store files.
+pub fn config_path() -> Result<PathBuf, StoreError> {
+    Ok(util::get_store_dir()?.join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let path = Path::new("config.toml");
+        assert_eq!(Config::parse("", path).unwrap(), Config::default());
+        assert_eq!(
+            Config::parse(
+                "default_limit = 10\ncolor = false\ncase_mode = \"smart\"\n\
+                format = \"{key}\"\n",
+                path
+            )
+            .unwrap(),
+            Config {
+                default_limit: Some(10),
+                color: Some(false),
+                case_mode: Some(CaseMatching::Smart),
+                format: Some(Format::parse("{key}").unwrap()),
+            }
+        );
+        for invalid in [
+            "default_limit = 0",
+            "default_limit = \"ten\"",
+            "case_mode = \"loud\"",
+            "format = \"{name}\"",
+            "colour = true",
+        ] {
+            assert!(matches!(
+                Config::parse(invalid, path),
+                Err(ConfigError::Invalid(_, _))
+            ));
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2022 Pranav Karawale
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The store behind `what-was-that`, a simple tool to remember little
+//! things.
+//!
+//! A [`store::Store`] maps the names of things to their descriptions, and
+//! finds them using the descriptions. It is kept in one of the backends in
+//! [`backend`], based on the extension of the store file:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use what_was_that::store::{FindOptions, Store};
+//!
+//! let mut store = Store::new(Path::new("store.json")).unwrap();
+//! store.set("ls", "list files").unwrap();
+//! for m in store.find("list", &FindOptions::default()).unwrap() {
+//!     println!("{} -> {}", m.key, m.description);
+//! }
+//! ```
+
+pub mod backend;
+pub mod store;
+pub mod util;
+
+extern crate fuzzy_matcher;
+extern crate rand;
+extern crate regex;
+extern crate rusqlite;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
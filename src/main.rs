@@ -12,55 +12,1438 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::path::Path;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
-use cli::Commands;
+use backend::BackendKind;
+use clap::{IntoApp, Parser};
+use cli::{Backend, Commands, ExportFormat, ProfilesCommand, SortBy};
+use serde::Serialize;
+use store::{
+    CaseMatching, Field, FindOptions, MultiStore, Operation, SearchIn,
+    SearchMode, SetOutcome, StoreError, StoreErrorKind, Summary,
+};
+use ui::ExitCode;
+
+use what_was_that::{backend, store, util};
 
 mod cli;
-mod store;
-mod util;
+mod config;
+mod ui;
 
+extern crate arboard;
 extern crate clap;
-extern crate fuzzy_matcher;
+extern crate clap_complete;
+extern crate serde;
 extern crate serde_json;
+extern crate toml;
+extern crate what_was_that;
 
-fn main() {
+fn main() -> std::process::ExitCode {
     let cli = cli::Cli::parse();
+    match run(cli) {
+        Ok(code) => code.into(),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit_code(&e).into()
+        }
+    }
+}
+
+/// Opens the store at the given path, kept in the given backend or in the one
+/// picked based on the extension of the store file.
+fn open(
+    store_path: &Path,
+    backend: Option<Backend>,
+) -> Result<store::Store<'_>, StoreError> {
+    store::Store::with_backend(store_path, backend_kind(store_path, backend))
+}
+
+/// Returns the kind of the given backend, or the one picked based on the
+/// extension of the store file if none is given.
+fn backend_kind(store_path: &Path, backend: Option<Backend>) -> BackendKind {
+    match backend {
+        Some(Backend::Json) => BackendKind::Json,
+        Some(Backend::Yaml) => BackendKind::Yaml,
+        Some(Backend::Sqlite) => BackendKind::Sqlite,
+        None => BackendKind::from_path(store_path),
+    }
+}
+
+/// Opens the store file in the editor, and loads it again when the editor
+/// exits to check it. If it can't be loaded, the user is asked whether to
+/// open the editor again to fix it.
+fn edit_store(
+    store_path: &Path,
+    backend: Option<Backend>,
+    quiet: bool,
+) -> Result<ExitCode, StoreError> {
+    if backend_kind(store_path, backend) == BackendKind::Sqlite {
+        return Err(StoreError::App(StoreErrorKind::Editor(
+            "SQLite stores aren't text files".to_string(),
+        )));
+    }
+    if !store_path.exists() {
+        // Loading the store creates the store file.
+        open(store_path, backend)?;
+    }
+    let editor = util::editor();
+    let mut words = editor.split_whitespace();
+    // The editor is never only whitespace.
+    let program = words.next().unwrap();
+    let args = words.collect::<Vec<_>>();
+    loop {
+        let status = std::process::Command::new(program)
+            .args(&args)
+            .arg(store_path)
+            .status()
+            .map_err(|e| {
+                StoreError::App(StoreErrorKind::Editor(format!(
+                    "{}: {}",
+                    editor, e
+                )))
+            })?;
+        if !status.success() {
+            return Err(StoreError::App(StoreErrorKind::Editor(format!(
+                "{} exited with {}",
+                editor, status
+            ))));
+        }
+        match open(store_path, backend) {
+            Ok(store) => {
+                if !quiet {
+                    eprintln!("The store has {} things.", store.count());
+                }
+                return Ok(ExitCode::Success);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                if ui::confirm("Open the editor again to fix it?") != Some(true)
+                {
+                    return Ok(ExitCode::Error);
+                }
+            }
+        }
+    }
+}
+
+/// Checks that the store can be used, printing the result of every check.
+/// The checks after a failed one are skipped if they depend on it.
+fn doctor(cli: &cli::Cli) -> ExitCode {
+    // The config file is loaded first, since it can turn the colors off.
+    let config = config::Config::load();
+    let no_color = matches!(&config, Ok(config) if config.color == Some(false));
+    let color = util::use_color(no_color);
+    let mut failed = false;
+    let mut report = |passed: bool, message: String| {
+        println!("{} {}", ui::check_label(passed, color), message);
+        failed |= !passed;
+    };
 
-    let store_path = cli.store_path.unwrap_or({
-        util::get_config_dir()
-            .join("wwt")
-            .join("store.json")
+    // Without a config file, there is nothing to report.
+    if let Err(e) = config {
+        report(false, e.to_string());
+    }
+
+    let store_path = match cli.store_path.first() {
+        Some(store_path) => Ok(PathBuf::from(store_path)),
+        None => util::get_profile_path(&cli.profile),
+    };
+    let store_path = match store_path {
+        Ok(store_path) => {
+            report(true, format!("Store path: {}", store_path.display()));
+            store_path
+        }
+        Err(e) => {
+            report(false, format!("Store path: {}", e));
+            return ExitCode::Error;
+        }
+    };
+
+    if store_path.is_dir() {
+        report(false, "The store path is a directory.".to_string());
+        return ExitCode::Error;
+    }
+    if !store_path.exists() {
+        report(
+            true,
+            "The store file doesn't exist yet. \
+            It's created when you remember something."
+                .to_string(),
+        );
+        return ExitCode::Success;
+    }
+
+    match std::fs::OpenOptions::new().append(true).open(&store_path) {
+        Ok(_) => report(true, "The store file is writable.".to_string()),
+        Err(e) => {
+            report(false, format!("The store file isn't writable: {}", e))
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        match std::fs::metadata(&store_path) {
+            Ok(metadata) if metadata.permissions().mode() & 0o077 == 0 => {
+                report(
+                    true,
+                    "The store file is only accessible by you.".to_string(),
+                )
+            }
+            Ok(metadata) => report(
+                false,
+                format!(
+                    "The store file is accessible by other users \
+                    (mode {:o}). Run `chmod 600 {}` to fix it.",
+                    metadata.permissions().mode() & 0o777,
+                    store_path.display()
+                ),
+            ),
+            Err(e) => {
+                report(false, format!("The permissions can't be read: {}", e))
+            }
+        }
+    }
+
+    match open(&store_path, cli.backend) {
+        Ok(store) => {
+            report(true, "The store file can be loaded.".to_string());
+            report(true, format!("Things in the store: {}", store.count()));
+        }
+        Err(e) => {
+            report(false, format!("The store file can't be loaded: {}", e))
+        }
+    }
+
+    if failed {
+        ExitCode::Error
+    } else {
+        ExitCode::Success
+    }
+}
+
+/// Wraps an error from the clipboard, which is missing on headless systems.
+fn clipboard_error(error: arboard::Error) -> StoreError {
+    StoreError::App(StoreErrorKind::Clipboard(error.to_string()))
+}
+
+/// Returns the status code that the program exits with after the error.
+/// Every variant is listed, so that new ones get a code picked for them.
+fn exit_code(error: &StoreError) -> ExitCode {
+    match error {
+        StoreError::Io(_)
+        | StoreError::Json(_)
+        | StoreError::Yaml(_)
+        | StoreError::Sqlite(_)
+        | StoreError::App(_) => ExitCode::Error,
+    }
+}
+
+/// Runs the command, returning the status code to exit with, or the error to
+/// report.
+fn run(cli: cli::Cli) -> Result<ExitCode, StoreError> {
+    // Completion scripts don't need the store, so don't bother loading it.
+    if let Commands::Completions { shell } = cli.command {
+        clap_complete::generate(
+            shell,
+            &mut cli::Cli::into_app(),
+            env!("CARGO_PKG_NAME"),
+            &mut std::io::stdout(),
+        );
+        return Ok(ExitCode::Success);
+    }
+
+    // Neither do the profiles.
+    if let Commands::Profiles(ProfilesCommand::List) = cli.command {
+        let profiles = util::list_profiles()?;
+        for profile in profiles {
+            println!("{}", profile);
+        }
+        return Ok(ExitCode::Success);
+    }
+
+    // The doctor reports the errors that would stop the store from loading,
+    // instead of failing with them.
+    if let Commands::Doctor = cli.command {
+        return Ok(doctor(&cli));
+    }
+
+    let profile = cli.profile;
+    let mut store_paths = cli.store_path.into_iter();
+    let store_path = match store_paths.next() {
+        Some(store_path) => store_path,
+        None => util::get_profile_path(&profile)?
             .to_str()
             .unwrap()
-            .to_string()
-    });
+            .to_string(),
+    };
 
-    let mut store = store::Store::new(Path::new(&store_path))
-        .unwrap_or_else(|e| util::print_and_exit(e.to_string().as_str()));
+    let store_path = Path::new(&store_path);
+    let other_paths = store_paths.map(PathBuf::from).collect::<Vec<_>>();
+    let (quiet, verbose, debug) = (cli.quiet, cli.verbose, cli.debug);
+    let porcelain = cli.porcelain;
+    if debug {
+        eprintln!("[debug] store path: {}", store_path.display());
+    }
+    let backend = cli.backend;
+    let open_store = || open(store_path, backend);
+    // The store file isn't loaded before it is edited, since it may be the
+    // corrupt file that is going to be fixed.
+    if let Commands::EditStore = cli.command {
+        return edit_store(store_path, backend, quiet);
+    }
+    // The flags win over the config file.
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(ExitCode::Error);
+        }
+    };
+    let mut store = match open_store() {
+        Err(StoreError::App(
+            StoreErrorKind::CorruptStore(_, _) | StoreErrorKind::NotUtf8(_, _),
+        )) if cli.repair => store::move_aside(store_path)
+            .map(|backup_path| {
+                if !quiet {
+                    eprintln!(
+                        "Moved the corrupt store file to {}.",
+                        backup_path.display()
+                    )
+                }
+            })
+            .and_then(|_| open_store()),
+        store => store,
+    }?;
+    store.set_compact(cli.compact);
+    store.backups = cli.backups;
+    store.max_length = Some(cli.max_length);
+    let dry_run = cli.dry_run;
+    // Not saving is how a dry run works, but the messages are the usual ones.
+    store.dry_run = dry_run || cli.no_save;
+    if cli.case_insensitive_keys && !store.settings().case_insensitive_keys {
+        store.set_case_insensitive_keys(true)?;
+        if verbose {
+            eprintln!("Names are case-insensitive in this store from now on.");
+        }
+    }
+    if cli.case_sensitive_keys && store.settings().case_insensitive_keys {
+        store.set_case_insensitive_keys(false)?;
+        if verbose {
+            eprintln!("Names are case-sensitive in this store from now on.");
+        }
+    }
+    // The other stores are only read by the commands that search or list the
+    // things, and left alone otherwise.
+    let others = match cli.command {
+        Commands::Find { .. } | Commands::List { .. } => other_paths
+            .iter()
+            .map(|path| open(path, backend))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => Vec::new(),
+    };
 
     match cli.command {
-        Commands::Remember { name, description } => {
-            store.set(&name, &description).unwrap_or_else(|e| {
-                util::print_and_exit(e.to_string().as_str())
+        Commands::Remember {
+            json: Some(json),
+            tags,
+            category,
+            no_clobber,
+            force,
+            ..
+        } => {
+            if force {
+                store.max_length = None;
+            }
+            let mut entries =
+                store::parse_json(&json)?.into_iter().collect::<Vec<_>>();
+            entries.sort();
+            if no_clobber {
+                if let Some((name, _)) =
+                    entries.iter().find(|(k, _)| store.entry(k).is_some())
+                {
+                    return Err(StoreError::App(StoreErrorKind::KeyExists(
+                        name.to_string(),
+                    )));
+                }
+            }
+            let entries = entries.into_iter().map(|(name, description)| {
+                let entry = store::Entry {
+                    description,
+                    tags: tags.clone(),
+                    category: category.clone(),
+                    ..Default::default()
+                };
+                (name, entry)
             });
+            let summary = store.set_many(entries.collect())?;
+            print_summary(&summary, quiet, dry_run);
+        }
+        Commands::Remember {
+            name,
+            description,
+            from_file,
+            tags,
+            category,
+            note,
+            no_clobber,
+            force,
+            ..
+        } => {
+            // Without JSON, the name is required.
+            let name = name.unwrap();
+            if force {
+                store.max_length = None;
+            }
+            if no_clobber && store.entry(&name).is_some() {
+                return Err(StoreError::App(StoreErrorKind::KeyExists(name)));
+            }
+            let description = match (from_file, description.as_deref()) {
+                (Some(path), _) => ui::read_file(&path)?,
+                (None, None | Some("-")) => {
+                    // Without a pipe, the description is typed in, so say
+                    // how to finish it instead of seemingly hanging.
+                    if util::stdin_is_tty() && !quiet {
+                        eprintln!(
+                            "Type the description, then press Ctrl-D to \
+                            finish (Ctrl-Z and Enter on Windows):"
+                        );
+                    }
+                    ui::read_stdin()?
+                }
+                (None, Some(description)) => {
+                    if util::stdin_is_piped() && !quiet {
+                        eprintln!(
+                            "Warning: ignoring the standard input, \
+                            since the description was given."
+                        );
+                    }
+                    description.to_string()
+                }
+            };
+            let entry = store::Entry {
+                description,
+                tags,
+                category,
+                note,
+                ..Default::default()
+            };
+            let outcome = store.set(&name, entry)?;
+            match outcome {
+                SetOutcome::Updated(old_description) if !quiet => {
+                    eprintln!("Updated '{}' (was: {})", name, old_description)
+                }
+                SetOutcome::Created if verbose => {
+                    eprintln!("Remembered '{}'.", name)
+                }
+                _ => {}
+            }
+        }
+        Commands::Edit {
+            name,
+            description,
+            force,
+        } => {
+            if force {
+                store.max_length = None;
+            }
+            store.update(&name, &description)?;
+            if verbose {
+                eprintln!("Updated '{}'.", name);
+            }
+        }
+        Commands::Alias { name, target } => {
+            store.alias(&name, &target)?;
+            if verbose {
+                eprintln!("Remembered '{}' as an alias of '{}'.", name, target);
+            }
+        }
+        Commands::Pin { name } => {
+            store.set_pinned(&name, true)?;
+            if verbose {
+                eprintln!("Pinned '{}'.", name);
+            }
         }
-        Commands::Find { description } => {
-            let matches = store.find(description.as_str());
+        Commands::Unpin { name } => {
+            store.set_pinned(&name, false)?;
+            if verbose {
+                eprintln!("Unpinned '{}'.", name);
+            }
+        }
+        Commands::Touch { name } => {
+            store.touch(&name)?;
+            if verbose {
+                eprintln!("Marked '{}' as found now.", name);
+            }
+        }
+        Commands::Rename {
+            old_name,
+            new_name,
+            force,
+        } => {
+            store.rename(&old_name, &new_name, force)?;
+            if verbose {
+                eprintln!("Renamed '{}' to '{}'.", old_name, new_name);
+            }
+        }
+        Commands::Find {
+            description,
+            limit,
+            tag,
+            category,
+            fuzzy: _,
+            words,
+            regex,
+            contains,
+            substring,
+            case_sensitive,
+            smart_case,
+            ignore_case,
+            min_score,
+            by_frequency,
+            invert,
+            keys_only,
+            values_only,
+            both: _,
+            fields,
+            combined,
+            no_color,
+            json,
+            first,
+            count,
+            copy,
+            output,
+            format,
+            group_by_tag,
+            explain,
+            run,
+            yes,
+        } => {
+            let format = format
+                .or_else(|| config.format.clone())
+                .unwrap_or(ui::Format::Default);
+            let options = FindOptions {
+                tag: tag.as_deref(),
+                category: category.as_deref(),
+                contains: contains.as_deref(),
+                search_in: if combined {
+                    SearchIn::Combined
+                } else if !fields.is_empty() {
+                    SearchIn::fields(&fields)
+                } else if keys_only {
+                    SearchIn::Keys
+                } else if values_only {
+                    SearchIn::Descriptions
+                } else {
+                    SearchIn::Both
+                },
+                mode: if regex {
+                    SearchMode::Regex
+                } else if substring {
+                    SearchMode::Substring
+                } else if words {
+                    SearchMode::Words
+                } else {
+                    SearchMode::Fuzzy
+                },
+                case: if case_sensitive {
+                    CaseMatching::Respect
+                } else if smart_case {
+                    CaseMatching::Smart
+                } else if ignore_case {
+                    CaseMatching::Ignore
+                } else {
+                    config.case_mode.unwrap_or(CaseMatching::Ignore)
+                },
+                min_score,
+                by_frequency,
+                limit: if first {
+                    Some(1)
+                } else {
+                    limit.or(config.default_limit)
+                },
+                invert,
+            };
+            let stores = std::iter::once(&store).chain(&others).collect();
+            let multi = MultiStore::new(stores);
+            if debug {
+                // Find everything that matches, to show the scores of the
+                // matches that are left out too.
+                let all_options = FindOptions {
+                    limit: None,
+                    min_score: None,
+                    ..options.clone()
+                };
+                for (_, m) in multi.find(description.as_str(), &all_options)? {
+                    eprintln!(
+                        "[debug] {}: score={} field={:?} uses={}",
+                        m.key,
+                        m.score.map_or("none".to_string(), |s| s.to_string()),
+                        m.field,
+                        m.uses
+                    );
+                }
+            }
+            let matches = multi.find(description.as_str(), &options)?;
+            if explain {
+                for (rank, (i, m)) in matches.iter().enumerate() {
+                    let entry = multi.stores()[*i].entry(&m.key).unwrap();
+                    let summary = format!(
+                        "{}. {}: score={} field={:?} pinned={} uses={}",
+                        rank + 1,
+                        m.key,
+                        m.score.map_or("none".to_string(), |s| s.to_string()),
+                        m.field,
+                        entry.pinned,
+                        m.uses
+                    );
+                    eprintln!("[explain] {}", label(&multi, *i, summary));
+                    let text = entry.field_text(&m.key, m.field);
+                    eprintln!(
+                        "[explain]    {}",
+                        ui::bracket(&text, &m.indices).replace('\n', "\\n")
+                    );
+                }
+            }
+            if count {
+                // Nothing is shown, so the matches aren't marked as found.
+                println!("{}", matches.len());
+                return Ok(ExitCode::Success);
+            }
+            let mut printed = String::new();
+            if first {
+                if let Some((_, m)) = matches.first() {
+                    printed = format!("{}\n", m.key);
+                }
+            } else if json {
+                printed = format_json(matches.iter().map(|(i, m)| JsonEntry {
+                    key: &m.key,
+                    description: &m.description,
+                    score: m.score,
+                    store: store_label(&multi, *i),
+                }));
+            } else if porcelain {
+                for (i, m) in matches.iter() {
+                    printed.push_str(&porcelain_entry(
+                        &multi,
+                        *i,
+                        &m.key,
+                        &m.description,
+                    ));
+                }
+            } else {
+                // Escape codes don't belong in files.
+                let color = output.is_none()
+                    && util::use_color(no_color || config.color == Some(false));
+                let mut formatted_matches = Vec::new();
+                for (i, m) in matches.iter() {
+                    let (key, description) = match (color, m.field) {
+                        (true, Field::Key) => (
+                            ui::highlight(&m.key, &m.indices),
+                            m.description.clone(),
+                        ),
+                        (true, Field::Description) => (
+                            m.key.clone(),
+                            ui::highlight(&m.description, &m.indices),
+                        ),
+                        // The combined text starts with the key and the
+                        // description, separated by a space.
+                        (true, Field::Combined) => {
+                            let key_len = m.key.chars().count();
+                            let key_indices = m
+                                .indices
+                                .iter()
+                                .copied()
+                                .filter(|i| *i < key_len)
+                                .collect::<Vec<_>>();
+                            let description_indices = m
+                                .indices
+                                .iter()
+                                .filter_map(|i| i.checked_sub(key_len + 1))
+                                .collect::<Vec<_>>();
+                            (
+                                ui::highlight(&m.key, &key_indices),
+                                ui::highlight(
+                                    &m.description,
+                                    &description_indices,
+                                ),
+                            )
+                        }
+                        // The note and the tags aren't printed, so nothing
+                        // is highlighted when they matched.
+                        (_, _) => (m.key.clone(), m.description.clone()),
+                    };
+                    let formatted = format_entry(
+                        multi.stores()[*i],
+                        &format,
+                        &m.key,
+                        &key,
+                        &description,
+                        m.score,
+                        verbose,
+                    );
+                    formatted_matches.push((
+                        tags_of(&multi, *i, &m.key),
+                        label(&multi, *i, formatted),
+                    ));
+                }
+                printed = if group_by_tag {
+                    group_by_tags(&formatted_matches)
+                } else {
+                    formatted_matches.into_iter().map(|(_, f)| f).collect()
+                };
+            }
+            write_output(output.as_deref(), &printed, matches.len(), quiet)?;
+            if matches.is_empty() && multi.count() == 0 {
+                if !quiet {
+                    eprintln!(
+                        "Your store is empty. \
+                        Add entries with `what-was-that remember`."
+                    );
+                }
+                return Ok(ExitCode::EmptyStore);
+            }
             if matches.is_empty() {
-                eprintln!("No matches found.");
-                std::process::exit(1);
+                if !quiet {
+                    eprintln!("No matches found.");
+                }
+                return Ok(ExitCode::NoMatches);
+            }
+            if copy {
+                let key = &matches[0].1.key;
+                match ui::copy_to_clipboard(key) {
+                    Ok(()) if !quiet => {
+                        eprintln!("Copied '{}' to the clipboard.", key)
+                    }
+                    Err(e) if !quiet => eprintln!(
+                        "Warning: could not copy to the clipboard: {}",
+                        e
+                    ),
+                    _ => {}
+                }
+            }
+            // Nothing is run unless it is clear which match is meant.
+            let matched = matches.len();
+            let ambiguous = run && matched > 1;
+            let command = Some(matches[0].1.key.clone()).filter(|_| run);
+            // Changes only go to the first store.
+            let keys = matches
+                .into_iter()
+                .filter(|(i, _)| *i == 0)
+                .map(|(_, m)| m.key)
+                .collect::<Vec<_>>();
+            store.mark_accessed(&keys)?;
+            if ambiguous {
+                eprintln!(
+                    "Not running anything, since {} things matched. Describe \
+                    it better, or give --first to run the best match.",
+                    matched
+                );
+                return Ok(ExitCode::Error);
+            }
+            if let Some(command) = command {
+                if dry_run {
+                    if !quiet {
+                        eprintln!("[dry-run] Would run '{}'.", command);
+                    }
+                    return Ok(ExitCode::Success);
+                }
+                if !yes {
+                    let question = format!("Run '{}'?", command);
+                    if let Err(code) = confirm(&question, "run it") {
+                        return Ok(code);
+                    }
+                }
+                let status = util::shell_command(&command).status()?;
+                if !status.success() {
+                    if !quiet {
+                        eprintln!("'{}' exited with {}.", command, status);
+                    }
+                    return Ok(ExitCode::Error);
+                }
+            }
+        }
+        Commands::Describe { name } => match store.get(&name) {
+            Some(description) => println!("{}", description),
+            None => {
+                return Err(StoreError::App(StoreErrorKind::KeyNotFound(name)))
+            }
+        },
+        Commands::List {
+            namespace,
+            sort,
+            reverse,
+            since,
+            until,
+            json,
+            output,
+            format,
+            group_by_tag,
+        } => {
+            let format = format
+                .or_else(|| config.format.clone())
+                .unwrap_or(ui::Format::Default);
+            let stores = std::iter::once(&store).chain(&others).collect();
+            let multi = MultiStore::new(stores);
+            let entry = |i: usize, k: &str| multi.stores()[i].entry(k);
+            let mut entries = match namespace {
+                Some(namespace) => multi.list_namespace(&namespace),
+                None => multi.list(),
+            };
+            if since.is_some() || until.is_some() {
+                let created = multi
+                    .list_created(since, until)
+                    .into_iter()
+                    .map(|(i, [k, _])| (i, k))
+                    .collect::<HashSet<_>>();
+                let unknown = entries
+                    .iter()
+                    .filter(|(i, [k, _])| {
+                        entry(*i, k).is_some_and(|e| e.created_at.is_none())
+                    })
+                    .count();
+                if unknown > 0 && verbose {
+                    eprintln!(
+                        "Left out {} things without a creation time.",
+                        unknown
+                    );
+                }
+                entries
+                    .retain(|(i, [k, _])| created.contains(&(*i, k.clone())));
+            }
+            if multi.count() == 0 && !quiet {
+                eprintln!(
+                    "Your store is empty. \
+                    Add entries with `what-was-that remember`."
+                );
+            }
+            // The entries are sorted by name already, and the sorts are
+            // stable, so ties stay sorted by name.
+            match sort {
+                SortBy::Name => {}
+                SortBy::Description => {
+                    entries.sort_by(|(_, [_, a]), (_, [_, b])| a.cmp(b));
+                }
+                SortBy::Length => {
+                    entries.sort_by_key(|(_, [_, v])| v.chars().count());
+                }
+                SortBy::Recent => entries.sort_by_key(|(i, [k, _])| {
+                    std::cmp::Reverse(
+                        entry(*i, k).and_then(|e| e.last_accessed),
+                    )
+                }),
+            }
+            if reverse {
+                entries.reverse();
+            }
+            let printed = if json {
+                format_json(entries.iter().map(|(i, [k, v])| JsonEntry {
+                    key: k,
+                    description: v,
+                    score: None,
+                    store: store_label(&multi, *i),
+                }))
+            } else if porcelain {
+                entries
+                    .iter()
+                    .map(|(i, [k, v])| porcelain_entry(&multi, *i, k, v))
+                    .collect()
             } else {
-                for [k, v] in matches {
-                    println!("{} -> {}", k, v);
+                let formatted_entries = entries
+                    .iter()
+                    .map(|(i, [k, v])| {
+                        let formatted = format_entry(
+                            multi.stores()[*i],
+                            &format,
+                            k,
+                            k,
+                            v,
+                            None,
+                            verbose,
+                        );
+                        (tags_of(&multi, *i, k), label(&multi, *i, formatted))
+                    })
+                    .collect::<Vec<_>>();
+                if group_by_tag {
+                    group_by_tags(&formatted_entries)
+                } else {
+                    formatted_entries.into_iter().map(|(_, f)| f).collect()
                 }
+            };
+            write_output(output.as_deref(), &printed, entries.len(), quiet)?;
+        }
+        Commands::Namespaces => {
+            for namespace in store.namespaces() {
+                println!("{}", namespace);
             }
         }
-        Commands::Forget { name } => {
-            store.delete(&name).unwrap_or_else(|e| {
-                util::print_and_exit(e.to_string().as_str())
-            });
+        Commands::Categories => {
+            for (category, count) in store.count_by_category() {
+                println!("{}: {}", category, count);
+            }
+        }
+        Commands::Count { tags } => {
+            println!("{}", store.count());
+            if tags {
+                for (tag, count) in store.count_by_tag() {
+                    println!("{}: {}", tag, count);
+                }
+            }
+        }
+        Commands::Random { count } => {
+            if store.count() == 0 && !quiet {
+                eprintln!(
+                    "Your store is empty. \
+                    Add entries with `what-was-that remember`."
+                );
+            }
+            for [k, v] in store.random(count) {
+                if porcelain {
+                    print!("{}", ui::porcelain(&[&k, &v]));
+                    continue;
+                }
+                print!(
+                    "{}",
+                    format_entry(
+                        &store,
+                        &ui::Format::Default,
+                        &k,
+                        &k,
+                        &v,
+                        None,
+                        verbose
+                    )
+                );
+            }
+        }
+        Commands::Stats => {
+            let stats = store.stats();
+            println!("Things: {}", stats.count);
+            println!("Average description length: {:.1}", stats.average_length);
+            if let Some((key, length)) = stats.longest {
+                println!(
+                    "Longest description: {} ({} characters)",
+                    key, length
+                );
+            }
+            if let Some((key, created_at)) = stats.oldest {
+                let created_at = util::format_timestamp(created_at);
+                println!("Oldest thing: {} ({})", key, created_at);
+            }
+            if let Some((key, created_at)) = stats.newest {
+                let created_at = util::format_timestamp(created_at);
+                println!("Newest thing: {} ({})", key, created_at);
+            }
+            if !stats.tags.is_empty() {
+                println!("Tags:");
+                for (tag, count) in stats.tags {
+                    println!("    {}: {}", tag, count);
+                }
+            }
         }
+        Commands::Dedupe { ignore_case, merge } => {
+            let groups = store.find_duplicates(ignore_case);
+            let mut duplicates = Vec::new();
+            for keys in groups.iter() {
+                println!(
+                    "{}: {}",
+                    store.entry(&keys[0]).unwrap().description,
+                    keys.join(", ")
+                );
+            }
+            if merge {
+                for keys in groups {
+                    for key in &keys[1..] {
+                        match (quiet, dry_run) {
+                            (true, _) => {}
+                            (false, true) => {
+                                eprintln!(
+                                    "[dry-run] Would forget {} (same as {})",
+                                    key, keys[0]
+                                );
+                            }
+                            (false, false) => {
+                                eprintln!(
+                                    "Forgetting {} (same as {})",
+                                    key, keys[0]
+                                );
+                            }
+                        }
+                    }
+                    duplicates.extend(keys.into_iter().skip(1));
+                }
+                store.delete_many(&duplicates)?;
+                let summary = Summary {
+                    forgotten: duplicates.len(),
+                    ..Default::default()
+                };
+                print_summary(&summary, quiet, dry_run);
+            }
+        }
+        Commands::Export {
+            format,
+            keys,
+            pattern,
+            regex,
+            remove,
+            output,
+            clipboard,
+        } => {
+            // Without a selection, everything is exported.
+            let selected = if let Some(pattern) = pattern {
+                let pattern = store::key_pattern(&pattern, regex)?;
+                store
+                    .list()
+                    .into_iter()
+                    .map(|[k, _]| k)
+                    .filter(|k| pattern.is_match(k))
+                    .collect()
+            } else if !keys.is_empty() {
+                let mut selected = Vec::new();
+                for key in keys {
+                    if store.entry(&key).is_none() {
+                        return Err(StoreError::App(
+                            StoreErrorKind::KeyNotFound(key),
+                        ));
+                    }
+                    selected.push(store.stored_key(&key));
+                }
+                selected
+            } else {
+                store.list().into_iter().map(|[k, _]| k).collect()
+            };
+            let selected_keys =
+                selected.iter().map(String::as_str).collect::<HashSet<_>>();
+            let is_selected = |k: &str| selected_keys.contains(k);
+            let exported = match format {
+                ExportFormat::Csv => Ok(store.export_csv_matching(is_selected)),
+                ExportFormat::Json => store.export_json_matching(is_selected),
+            }?;
+            match output {
+                // The things are only forgotten once they are safely in the
+                // file.
+                Some(output) if remove => {
+                    if !dry_run {
+                        std::fs::write(&output, exported)?;
+                    }
+                    store.delete_many(&selected)?;
+                    match (quiet, dry_run) {
+                        (true, _) => {}
+                        (false, true) => println!(
+                            "[dry-run] Would move {} things to {}.",
+                            selected.len(),
+                            output
+                        ),
+                        (false, false) => println!(
+                            "Moved {} things to {}.",
+                            selected.len(),
+                            output
+                        ),
+                    }
+                }
+                Some(output) => std::fs::write(output, exported)?,
+                None if clipboard => {
+                    ui::copy_to_clipboard(&exported)
+                        .map_err(clipboard_error)?;
+                    if !quiet {
+                        eprintln!(
+                            "Copied {} things to the clipboard.",
+                            selected.len()
+                        );
+                    }
+                }
+                None => print!("{}", exported),
+            }
+        }
+        Commands::Import {
+            path,
+            clipboard: _,
+            overwrite,
+            ignore_case,
+        } => {
+            let (content, is_csv) = match path {
+                Some(path) => {
+                    (std::fs::read_to_string(&path)?, path.ends_with(".csv"))
+                }
+                None => {
+                    let content =
+                        ui::paste_from_clipboard().map_err(clipboard_error)?;
+                    let is_csv = !content.trim_start().starts_with('{');
+                    (content, is_csv)
+                }
+            };
+            let entries = if is_csv {
+                store::parse_csv(&content)
+            } else {
+                store::parse_json(&content)
+            }?;
+            let summary = store.import(entries, overwrite, ignore_case)?;
+            print_summary(&summary, quiet, dry_run);
+        }
+        Commands::Batch { path } => {
+            let batch = match path.as_deref() {
+                None | Some("-") => ui::read_stdin()?,
+                Some(path) => std::fs::read_to_string(path)?,
+            };
+            let (lines, operations): (Vec<_>, Vec<_>) =
+                store::parse_batch(&batch).into_iter().unzip();
+            // Only the operations that could be parsed are applied, but all
+            // of them are reported in the order of their lines.
+            let valid = operations
+                .iter()
+                .filter_map(|operation| operation.as_ref().ok().cloned())
+                .collect::<Vec<_>>();
+            let mut outcomes = store.batch(&valid)?.into_iter();
+            let mut failed = false;
+            for (line, operation) in lines.into_iter().zip(operations) {
+                let outcome = operation.and_then(|operation| {
+                    outcomes.next().unwrap().map(|_| operation)
+                });
+                match outcome {
+                    Ok(Operation::Set(key, _)) if !quiet => {
+                        println!("line {}: remembered '{}'", line, key)
+                    }
+                    Ok(Operation::Forget(key)) if !quiet => {
+                        println!("line {}: forgot '{}'", line, key)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        failed = true;
+                        eprintln!("line {}: {}", line, e);
+                    }
+                }
+            }
+            if failed {
+                return Ok(ExitCode::Error);
+            }
+        }
+        Commands::Restore { number } => {
+            store.restore(number)?;
+            match (quiet, dry_run) {
+                (true, _) => {}
+                (false, true) => {
+                    eprintln!(
+                        "[dry-run] Would restore backup number {}.",
+                        number
+                    );
+                }
+                (false, false) => {
+                    eprintln!("Restored backup number {}.", number);
+                }
+            }
+        }
+        Commands::Undo => {
+            store.undo()?;
+            match (quiet, dry_run) {
+                (true, _) => {}
+                (false, true) => {
+                    eprintln!("[dry-run] Would undo the last change.");
+                }
+                (false, false) => {
+                    eprintln!("Undid the last change.");
+                }
+            }
+        }
+        Commands::Completions { .. }
+        | Commands::Profiles(_)
+        | Commands::Doctor
+        | Commands::EditStore => {
+            unreachable!()
+        }
+        Commands::Forget {
+            pattern: Some(pattern),
+            regex,
+            yes,
+            ..
+        } => {
+            let pattern = store::key_pattern(&pattern, regex)?;
+            let matching = store
+                .list()
+                .into_iter()
+                .filter(|[k, _]| pattern.is_match(k))
+                .collect::<Vec<_>>();
+            // Show everything that would be forgotten first, so that a
+            // pattern matching more than expected is caught.
+            if !matching.is_empty() && !yes && !dry_run {
+                for [k, v] in matching.iter() {
+                    eprintln!("{} -> {}", k, v);
+                }
+                let question = if matching.len() == store.count() {
+                    format!("Delete all {} entries?", matching.len())
+                } else {
+                    format!("Delete these {} entries?", matching.len())
+                };
+                if let Err(code) = confirm(&question, "forget") {
+                    return Ok(code);
+                }
+            }
+            let keys = store.delete_matching(|k| pattern.is_match(k))?;
+            for key in keys.iter().filter(|_| !quiet) {
+                if dry_run {
+                    eprintln!("[dry-run] Would forget {}", key);
+                } else {
+                    eprintln!("Forgetting {}", key);
+                }
+            }
+        }
+        Commands::Forget { name, yes, .. } => {
+            // Without a pattern, the name is required.
+            let name = name.unwrap();
+            let description = store.entry(&name).map(|e| &e.description);
+            if let (Some(description), false) = (description, yes || dry_run) {
+                eprintln!("{} -> {}", name, description);
+                if let Err(code) =
+                    confirm(&format!("Delete '{}'?", name), "forget")
+                {
+                    return Ok(code);
+                }
+            }
+            store.delete(&name)?;
+            if verbose {
+                eprintln!("Forgot '{}'.", name);
+            }
+        }
+        Commands::Prune {
+            older_than,
+            include_unknown,
+        } => {
+            let cutoff = std::time::SystemTime::now()
+                .checked_sub(older_than)
+                .unwrap_or(std::time::UNIX_EPOCH);
+            let keys = store.prune(cutoff, include_unknown)?;
+            for key in keys.iter().filter(|_| !quiet) {
+                if dry_run {
+                    eprintln!("[dry-run] Would forget {}", key);
+                } else {
+                    eprintln!("Forgetting {}", key);
+                }
+            }
+            let summary = Summary {
+                forgotten: keys.len(),
+                ..Default::default()
+            };
+            print_summary(&summary, quiet, dry_run);
+        }
+        Commands::Clear { yes } => {
+            if store.count() > 0 && !yes && !dry_run {
+                let question = format!("Delete all {} entries?", store.count());
+                if let Err(code) = confirm(&question, "forget") {
+                    return Ok(code);
+                }
+            }
+            let summary = store.clear()?;
+            print_summary(&summary, quiet, dry_run);
+        }
+    }
+    if dry_run && !quiet {
+        eprintln!("[dry-run] No changes were saved.");
+    }
+    if cli.no_save && !quiet {
+        eprintln!("[no-save] Nothing was saved.");
+    }
+    Ok(ExitCode::Success)
+}
+
+/// Prints the one-line summary of a bulk operation to the standard error,
+/// unless `quiet` is set.
+fn print_summary(summary: &Summary, quiet: bool, dry_run: bool) {
+    match (quiet, dry_run) {
+        (true, _) => {}
+        (false, true) => eprintln!("[dry-run] {}", summary),
+        (false, false) => eprintln!("{}", summary),
+    }
+}
+
+/// Asks the user to confirm the given question. Unless they answer yes,
+/// returns the code that the program should exit with.
+fn confirm(question: &str, action: &str) -> Result<(), ExitCode> {
+    match ui::confirm(question) {
+        Some(true) => Ok(()),
+        Some(false) => {
+            eprintln!("Aborted.");
+            Err(ExitCode::Error)
+        }
+        None => {
+            eprintln!(
+                "Refusing to {} without confirmation; pass --yes to skip it.",
+                action
+            );
+            Err(ExitCode::Unconfirmed)
+        }
+    }
+}
+
+/// Formats an entry of the store as a line, followed by lines with its note
+/// and timestamps if `verbose` is set. The key and the value are printed as
+/// given, so that they can be highlighted.
+fn format_entry(
+    store: &store::Store,
+    format: &ui::Format,
+    key: &str,
+    printed_key: &str,
+    value: &str,
+    score: Option<i64>,
+    verbose: bool,
+) -> String {
+    let entry = store.entry(key);
+    let alias_of = entry.and_then(|e| e.alias_of.as_deref());
+    let mut formatted = if *format == ui::Format::Default {
+        // Line up the lines of descriptions with several lines under the
+        // first one, instead of at the start of the line.
+        let indent = format!("\n{:1$}", "", key.chars().count() + 4);
+        let value = value.replace('\n', &indent);
+        format.render(printed_key, &value, score, alias_of)
+    } else {
+        format.render(printed_key, value, score, alias_of)
+    };
+    if !verbose {
+        formatted.push('\n');
+        return formatted;
+    }
+    if entry.is_some_and(|e| e.pinned) {
+        formatted.push_str(" *");
+    }
+    formatted.push('\n');
+    if let Some(note) = entry.and_then(|e| e.note.as_ref()) {
+        formatted.push_str(&format!("    note: {}\n", note));
+    }
+    let timestamp = |timestamp: Option<u64>| {
+        timestamp
+            .map(util::format_timestamp)
+            .unwrap_or_else(|| "unknown".to_string())
+    };
+    formatted.push_str(&format!(
+        "    created: {}\n    last found: {}\n    times found: {}\n",
+        timestamp(entry.and_then(|e| e.created_at)),
+        timestamp(entry.and_then(|e| e.last_accessed)),
+        entry.map_or(0, |e| e.uses)
+    ));
+    formatted
+}
+
+/// Returns the store file of the store at the given index, if several stores
+/// are read, so that the things from it can be told apart.
+fn store_label<'a>(multi: &MultiStore<'_, 'a>, i: usize) -> Option<&'a Path> {
+    Some(multi.stores()[i].store_path).filter(|_| multi.stores().len() > 1)
+}
+
+/// Prefixes the formatted entry of the store at the given index with its
+/// store file, like grep does with the files it searches, if several stores
+/// are read.
+fn label(multi: &MultiStore, i: usize, formatted: String) -> String {
+    match store_label(multi, i) {
+        Some(path) => format!("{}: {}", path.display(), formatted),
+        None => formatted,
+    }
+}
+
+/// Formats an entry of the store at the given index as a `--porcelain` line,
+/// with its store file as the first field if several stores are read.
+fn porcelain_entry(
+    multi: &MultiStore,
+    i: usize,
+    key: &str,
+    value: &str,
+) -> String {
+    let path = store_label(multi, i).map(|path| path.to_string_lossy());
+    let fields = path
+        .iter()
+        .map(|path| path.as_ref())
+        .chain([key, value])
+        .collect::<Vec<_>>();
+    ui::porcelain(&fields)
+}
+
+/// Returns the tags of the entry with the given key in the store at the given
+/// index.
+fn tags_of<'s>(
+    multi: &MultiStore<'s, '_>,
+    i: usize,
+    key: &str,
+) -> &'s [String] {
+    multi.stores()[i]
+        .entry(key)
+        .map_or(&[], |entry| &entry.tags)
+}
+
+/// Groups the formatted entries under a line for each of their tags, with the
+/// lines of the entries indented beneath. Entries with several tags are under
+/// each of them, and the untagged ones are under `(untagged)`, after the
+/// tags. The tags are sorted, and the entries keep their order.
+fn group_by_tags(entries: &[(&[String], String)]) -> String {
+    let mut groups = BTreeMap::new();
+    let mut untagged = Vec::new();
+    for (tags, formatted) in entries {
+        if tags.is_empty() {
+            untagged.push(formatted);
+        }
+        for tag in tags.iter() {
+            groups
+                .entry(tag.as_str())
+                .or_insert_with(Vec::new)
+                .push(formatted);
+        }
+    }
+    let untagged =
+        Some(("(untagged)", untagged)).filter(|(_, f)| !f.is_empty());
+    let mut grouped = String::new();
+    for (tag, formatted) in groups.into_iter().chain(untagged) {
+        grouped.push_str(&format!("{}:\n", tag));
+        for line in formatted.iter().flat_map(|f| f.lines()) {
+            grouped.push_str(&format!("    {}\n", line));
+        }
+    }
+    grouped
+}
+
+/// An entry of the store, as printed with `--json`.
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    key: &'a str,
+    description: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<i64>,
+    /// The store file the entry is from, when several stores are read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store: Option<&'a Path>,
+}
+
+/// Formats the entries as a JSON array.
+fn format_json<'a, I: Iterator<Item = JsonEntry<'a>>>(entries: I) -> String {
+    let entries = entries.collect::<Vec<_>>();
+    // Serializing plain strings and numbers can't fail.
+    serde_json::to_string_pretty(&entries).unwrap() + "\n"
+}
+
+/// Writes the formatted things to the given file, creating its parent
+/// directories and replacing what was in it, or to the standard output if no
+/// file is given. How many things were written to the file is reported on the
+/// standard error unless `quiet` is set, so that the standard output stays
+/// clean.
+fn write_output(
+    output: Option<&str>,
+    formatted: &str,
+    count: usize,
+    quiet: bool,
+) -> Result<(), StoreError> {
+    let path = match output {
+        Some(output) => Path::new(output),
+        None => {
+            print!("{}", formatted);
+            return Ok(());
+        }
+    };
+    let result = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            std::fs::create_dir_all(parent)
+        }
+        _ => Ok(()),
+    }
+    .and_then(|_| std::fs::write(path, formatted));
+    result?;
+    if !quiet {
+        eprintln!("Wrote {} things to {}.", count, path.display());
     }
+    Ok(())
 }
@@ -12,17 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
+use rand::seq::SliceRandom;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
 
+use backend::{BackendKind, StorageBackend};
+use util;
+
+/// Errors that can occur when using the store.
 #[derive(Debug)]
 pub enum StoreError {
+    /// Reading or writing a file failed.
     Io(std::io::Error),
+    /// Converting to or from JSON failed.
     Json(serde_json::Error),
+    /// Converting to or from YAML failed.
+    Yaml(serde_yaml::Error),
+    /// Using the SQLite database failed.
+    Sqlite(rusqlite::Error),
+    /// The store was used in a way it can't be.
     App(StoreErrorKind),
 }
 
@@ -35,13 +51,27 @@ impl fmt::Display for StoreError {
                 _ => write!(f, "IO error: {}", e),
             },
             StoreError::Json(e) => write!(f, "JSON error: {}", e),
+            StoreError::Yaml(e) => write!(f, "YAML error: {}", e),
+            StoreError::Sqlite(e) => write!(f, "SQLite error: {}", e),
             StoreError::App(e) => {
-                write!(f, "Application error: {}", e.to_string())
+                write!(f, "Application error: {}", e)
             }
         }
     }
 }
 
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Io(e) => Some(e),
+            StoreError::Json(e) => Some(e),
+            StoreError::Yaml(e) => Some(e),
+            StoreError::Sqlite(e) => Some(e),
+            StoreError::App(_) => None,
+        }
+    }
+}
+
 // Implement error conversion for StoreError
 impl From<std::io::Error> for StoreError {
     fn from(err: std::io::Error) -> Self {
@@ -53,12 +83,63 @@ impl From<serde_json::Error> for StoreError {
         StoreError::Json(err)
     }
 }
+impl From<serde_yaml::Error> for StoreError {
+    fn from(err: serde_yaml::Error) -> Self {
+        StoreError::Yaml(err)
+    }
+}
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Sqlite(err)
+    }
+}
 
 #[derive(Debug)]
 /// List of possible custom errors that can occur when using the store.
 pub enum StoreErrorKind {
     /// The specified key does not exist in the store.
     KeyNotFound(String),
+    /// The specified key already exists in the store.
+    KeyExists(String),
+    /// The store file is being used by another process.
+    Locked,
+    /// The CSV being imported is malformed.
+    InvalidCsv(String),
+    /// An operation of a batch is malformed.
+    InvalidOperation(String),
+    /// The config directory, where the store file is kept by default, could
+    /// not be determined.
+    NoConfigDir,
+    /// The store file at the given path could not be parsed, for the given
+    /// reason.
+    CorruptStore(PathBuf, String),
+    /// The store file at the given path is not valid UTF-8, starting at the
+    /// given byte.
+    NotUtf8(PathBuf, usize),
+    /// The given store path is a directory instead of a file.
+    StorePathIsDirectory(PathBuf),
+    /// The store was written by a newer version of the program, with the
+    /// given version of the store file.
+    UnsupportedVersion(u64),
+    /// The regex to find the entries with is malformed.
+    InvalidRegex(String),
+    /// There is no backup with the given number.
+    NoBackup(usize),
+    /// There is no snapshot of the store to undo the last change with.
+    NothingToUndo,
+    /// The given key is empty, only whitespace, or has line breaks in it.
+    InvalidKey(String),
+    /// The description is longer than the limit. Holds the length and the
+    /// limit, in characters.
+    ValueTooLong(usize, usize),
+    /// The system clipboard could not be used, for the given reason.
+    Clipboard(String),
+    /// The store file could not be edited in the editor, for the given
+    /// reason.
+    Editor(String),
+    /// The given keys are the same when the case is ignored, so keys can't be
+    /// made case-insensitive.
+    KeysDifferInCase(String, String),
 }
 
 impl fmt::Display for StoreErrorKind {
@@ -67,154 +148,3650 @@ impl fmt::Display for StoreErrorKind {
             StoreErrorKind::KeyNotFound(key) => {
                 write!(f, "Key not found: {}", key)
             }
+            StoreErrorKind::KeyExists(key) => {
+                write!(f, "Key already exists: {}", key)
+            }
+            StoreErrorKind::Locked => {
+                write!(f, "The store is locked by another process")
+            }
+            StoreErrorKind::InvalidCsv(reason) => {
+                write!(f, "Invalid CSV: {}", reason)
+            }
+            StoreErrorKind::InvalidOperation(reason) => {
+                write!(f, "Invalid operation: {}", reason)
+            }
+            StoreErrorKind::NoConfigDir => write!(
+                f,
+                "Could not determine config directory; set WWT_STORE_PATH"
+            ),
+            StoreErrorKind::CorruptStore(path, reason) => write!(
+                f,
+                "The store file {} is corrupt: {}\n\
+                Fix it, or move it aside (e.g. to {}) to start over. \
+                Run with --repair to move it aside automatically.",
+                path.display(),
+                reason,
+                backup_path(path).display()
+            ),
+            StoreErrorKind::NotUtf8(path, offset) => write!(
+                f,
+                "The store file {} is not valid UTF-8, starting at byte {}\n\
+                Something else may have written to it. Fix it, or move it \
+                aside (e.g. to {}) to start over. Run with --repair to move \
+                it aside automatically.",
+                path.display(),
+                offset,
+                backup_path(path).display()
+            ),
+            StoreErrorKind::StorePathIsDirectory(path) => write!(
+                f,
+                "The store path {} is a directory, expected a file",
+                path.display()
+            ),
+            StoreErrorKind::UnsupportedVersion(version) => write!(
+                f,
+                "The store is version {}, but only versions up to {} are \
+                supported; upgrade what-was-that to use it",
+                version, STORE_VERSION
+            ),
+            StoreErrorKind::InvalidRegex(reason) => {
+                write!(f, "Invalid regex: {}", reason)
+            }
+            StoreErrorKind::NoBackup(n) => write!(f, "No backup number {}", n),
+            StoreErrorKind::InvalidKey(key) => write!(
+                f,
+                "Invalid name {:?}: names can't be blank or span multiple lines",
+                key
+            ),
+            StoreErrorKind::NothingToUndo => {
+                write!(f, "There is nothing to undo")
+            }
+            StoreErrorKind::ValueTooLong(length, limit) => write!(
+                f,
+                "The description is {} characters long, longer than the \
+                limit of {}; give --force to remember it anyway",
+                length, limit
+            ),
+            StoreErrorKind::Clipboard(reason) => {
+                write!(f, "The clipboard is not available: {}", reason)
+            }
+            StoreErrorKind::Editor(reason) => {
+                write!(f, "Could not edit the store file: {}", reason)
+            }
+            StoreErrorKind::KeysDifferInCase(a, b) => write!(
+                f,
+                "'{}' and '{}' only differ in case; rename or forget one of \
+                them to make the names case-insensitive",
+                a, b
+            ),
         }
     }
 }
 
-/// Store Implementation for the CLI.
+/// An entry in the store.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The description of the thing.
+    pub description: String,
+    /// The tags of the thing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The category of the thing. Unlike the tags, a thing is in at most one
+    /// category.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Extra context about the thing, like links or caveats. Unlike the
+    /// description, the note is not matched by `find`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// When the entry was created, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// When the entry was last found, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_accessed: Option<u64>,
+    /// How many times the entry was found.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub uses: u64,
+    /// Whether the entry is found before the entries that aren't pinned,
+    /// whatever their scores.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// The key of the entry that this entry is an alias of. The description
+    /// of an alias is kept the same as the description of that entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+}
+
+/// Whether the number is zero, so that it can be left out of the store file.
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// Settings of a store, which are kept in the store file along with the
+/// entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Whether keys that only differ in case are the same key. The key is
+    /// kept in the case it was first given in.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub case_insensitive_keys: bool,
+}
+
+impl Entry {
+    /// Creates a new entry with the given description and nothing else.
+    pub fn new(description: &str) -> Entry {
+        Entry {
+            description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the given key of the entry, its description and its tags as
+    /// one text separated by spaces, as matched by [`SearchIn::Combined`].
+    /// The key comes first and the description right after it, so that the
+    /// positions of their characters are easy to work out.
+    pub fn combined(&self, key: &str) -> String {
+        std::iter::once(key)
+            .chain(std::iter::once(self.description.as_str()))
+            .chain(self.tags.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns the text of the given field of the entry, as matched by
+    /// `find`. The key of the entry has to be given, since it isn't kept in
+    /// the entry.
+    pub fn field_text(&self, key: &str, field: Field) -> String {
+        match field {
+            Field::Key => key.to_string(),
+            Field::Description => self.description.clone(),
+            Field::Note => self.note.clone().unwrap_or_default(),
+            Field::Tags => self.tags.join(" "),
+            Field::Combined => self.combined(key),
+        }
+    }
+}
+
+impl From<&str> for Entry {
+    fn from(description: &str) -> Self {
+        Entry::new(description)
+    }
+}
+
+/// An entry as it is found in the store file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum StoredEntry {
+    /// An entry as it is found in the current store files.
+    Entry(Entry),
+    /// Store files written before entries had tags and timestamps only
+    /// contain the descriptions.
+    Description(String),
+}
+
+impl From<StoredEntry> for Entry {
+    fn from(entry: StoredEntry) -> Self {
+        match entry {
+            StoredEntry::Entry(entry) => entry,
+            StoredEntry::Description(description) => Entry::new(&description),
+        }
+    }
+}
+
+/// Fields of an entry that can be matched by `find`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    /// The key of the entry.
+    Key,
+    /// The description of the entry.
+    Description,
+    /// The note of the entry.
+    Note,
+    /// The tags of the entry, matched as one text separated by spaces.
+    Tags,
+    /// The key, the description and the tags of the entry, matched as one
+    /// text separated by spaces. See [`Entry::combined`].
+    Combined,
+}
+
+/// The fields that `find` matches against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchIn {
+    /// Only the keys.
+    Keys,
+    /// Only the descriptions.
+    Descriptions,
+    /// Both the keys and the descriptions.
+    Both,
+    /// The key, the description and the tags together, as one text that
+    /// gets a single score. A query with words from several of them ranks
+    /// better this way than with the best of their separate scores.
+    Combined,
+    /// Any combination of the fields, each scored on its own.
+    Fields {
+        key: bool,
+        description: bool,
+        note: bool,
+        tags: bool,
+    },
+}
+
+impl SearchIn {
+    /// Searches only the given fields.
+    pub fn fields(fields: &[Field]) -> SearchIn {
+        SearchIn::Fields {
+            key: fields.contains(&Field::Key),
+            description: fields.contains(&Field::Description),
+            note: fields.contains(&Field::Note),
+            tags: fields.contains(&Field::Tags),
+        }
+    }
+
+    /// Whether the given field is searched.
+    fn includes(self, field: Field) -> bool {
+        match (self, field) {
+            (SearchIn::Keys, _) => field == Field::Key,
+            (SearchIn::Descriptions, _) => field == Field::Description,
+            (SearchIn::Both, _) => {
+                field == Field::Key || field == Field::Description
+            }
+            (SearchIn::Combined, _) => field == Field::Combined,
+            (SearchIn::Fields { .. }, Field::Combined) => false,
+            (SearchIn::Fields { key, .. }, Field::Key) => key,
+            (SearchIn::Fields { description, .. }, Field::Description) => {
+                description
+            }
+            (SearchIn::Fields { note, .. }, Field::Note) => note,
+            (SearchIn::Fields { tags, .. }, Field::Tags) => tags,
+        }
+    }
+}
+
+/// How `find` matches the query against the entries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchMode {
+    /// The query matches if its characters appear in order, and the matches
+    /// are scored by how close together the characters are.
+    Fuzzy,
+    /// The query is a regex. All the matches have the same score.
+    Regex,
+    /// The query matches if it is contained in the text. All the matches
+    /// have the same score.
+    Substring,
+    /// Each word of the query has to match the text fuzzily on its own, and
+    /// the matches are scored by the sum of the scores of the words.
+    Words,
+}
+
+/// Whether `find` tells uppercase and lowercase letters apart. Regexes
+/// decide this on their own, e.g. with `(?i)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaseMatching {
+    /// Uppercase and lowercase letters are the same.
+    Ignore,
+    /// Uppercase and lowercase letters are different.
+    Respect,
+    /// Uppercase and lowercase letters are only different if the query has
+    /// uppercase letters in it.
+    Smart,
+}
+
+impl CaseMatching {
+    /// Whether the case is ignored when matching the given query.
+    fn ignores_case(self, query: &str) -> bool {
+        match self {
+            CaseMatching::Ignore => true,
+            CaseMatching::Respect => false,
+            CaseMatching::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+}
 
+/// Options to narrow down the matches of `find`.
+#[derive(Clone, Debug)]
+pub struct FindOptions<'a> {
+    /// Only match the entries with this tag.
+    pub tag: Option<&'a str>,
+    /// Only match the entries in this category. Entries without a category
+    /// are never matched then.
+    pub category: Option<&'a str>,
+    /// Only match the entries whose descriptions contain this text, ignoring
+    /// the case.
+    pub contains: Option<&'a str>,
+    /// The fields to match against.
+    pub search_in: SearchIn,
+    /// How to match the query.
+    pub mode: SearchMode,
+    /// Whether the case of the letters matters.
+    pub case: CaseMatching,
+    /// Only keep the matches with at least this score.
+    pub min_score: Option<i64>,
+    /// Sort the matches by how many times they were found, instead of by
+    /// their scores.
+    pub by_frequency: bool,
+    /// Only return this many of the best matches.
+    pub limit: Option<usize>,
+    /// Return the entries that the query doesn't match instead, i.e. those
+    /// that the matcher rejects. They all score 0.
+    pub invert: bool,
+}
+
+impl Default for FindOptions<'_> {
+    fn default() -> Self {
+        FindOptions {
+            tag: None,
+            category: None,
+            contains: None,
+            search_in: SearchIn::Both,
+            mode: SearchMode::Fuzzy,
+            case: CaseMatching::Ignore,
+            min_score: None,
+            by_frequency: false,
+            limit: None,
+            invert: false,
+        }
+    }
+}
+
+/// A thing found in the store.
+#[derive(Debug, PartialEq)]
+pub struct Match {
+    /// The name of the thing.
+    pub key: String,
+    /// The description of the thing.
+    pub description: String,
+    /// The field that matched best.
+    pub field: Field,
+    /// How well the field matched, higher is better. Only fuzzy matches have
+    /// a score, since regexes, substrings, empty queries and inverted
+    /// queries match all the things equally.
+    pub score: Option<i64>,
+    /// How many times the thing was found before.
+    pub uses: u64,
+    /// The positions of the characters of the field that matched.
+    pub indices: Vec<usize>,
+}
+
+/// An operation of a batch, as parsed by [`parse_batch`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    /// Remember a thing with the given key and description.
+    Set(String, String),
+    /// Forget the thing with the given key.
+    Forget(String),
+}
+
+/// What happened when an entry was set.
+#[derive(Debug, PartialEq)]
+pub enum SetOutcome {
+    /// There was no entry with the key, so it was added.
+    Created,
+    /// The entry with the key was replaced. Holds its old description.
+    Updated(String),
+}
+
+/// Aggregate figures about the entries in the store.
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    /// The number of entries.
+    pub count: usize,
+    /// The average length of the descriptions, in characters.
+    pub average_length: f64,
+    /// The key of the entry with the longest description, and the length of
+    /// the description in characters.
+    pub longest: Option<(String, usize)>,
+    /// The key of the entry created first, and when it was created.
+    pub oldest: Option<(String, u64)>,
+    /// The key of the entry created last, and when it was created.
+    pub newest: Option<(String, u64)>,
+    /// The number of entries with each tag, sorted by the tags.
+    pub tags: Vec<(String, usize)>,
+}
+
+/// What a bulk operation did to the entries, shown as a one-line summary
+/// like `3 added, 1 updated, 2 skipped.`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// The number of entries that were added.
+    pub added: usize,
+    /// The number of existing entries that were replaced.
+    pub updated: usize,
+    /// The number of entries that were left as they were.
+    pub skipped: usize,
+    /// The number of entries that were deleted.
+    pub forgotten: usize,
+    /// How many of the entries were matched to an existing entry with the
+    /// key in another case.
+    pub case_folded: usize,
+}
+
+impl fmt::Display for Summary {
+    /// Lists the counts that aren't zero, or says that nothing changed.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = [
+            (self.added, "added"),
+            (self.updated, "updated"),
+            (self.skipped, "skipped"),
+            (self.forgotten, "forgotten"),
+            (self.case_folded, "matched in another case"),
+        ];
+        let counts = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, what)| format!("{} {}", count, what))
+            .collect::<Vec<_>>();
+        if counts.is_empty() {
+            write!(f, "nothing changed.")
+        } else {
+            write!(f, "{}.", counts.join(", "))
+        }
+    }
+}
+
+/// Store Implementation for the CLI.
 pub struct Store<'a> {
     /// The path to the store file.
     pub store_path: &'a Path,
+    /// The longest description that can be set, in characters, if any. This
+    /// guards against remembering a whole file by accident.
+    pub max_length: Option<usize>,
+    /// How many backups of the store file to keep. A backup is made before
+    /// entries are deleted.
+    pub backups: usize,
+    /// Whether the changes are only made in memory, without saving them to
+    /// the store file or making backups.
+    pub dry_run: bool,
     /// The in-memory store loaded from the store file.
-    store: HashMap<String, String>,
+    store: HashMap<String, Entry>,
+    /// The settings loaded from the store file.
+    settings: Settings,
+    /// The keys of the entries changed since the store was last saved.
+    changed: HashSet<String>,
+    /// The backend the store is loaded from and saved to.
+    backend: Box<dyn StorageBackend>,
+    /// The kind of the backend, so that it can be opened again.
+    kind: BackendKind,
+    /// The lock file, which is locked for as long as the store is in use.
+    lock: std::fs::File,
 }
 
+/// The longest description that can be set by default, in characters.
+pub const DEFAULT_MAX_LENGTH: usize = 4096;
+
+/// How many backups of the store file are kept by default.
+pub const DEFAULT_BACKUPS: usize = 3;
+
+/// The version of the store files that are written. Version 1 store files,
+/// which only contain the entries, are read too.
+pub const STORE_VERSION: u64 = 2;
+
+/// How long to wait for another process to release the store.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
 impl Store<'_> {
-    /// Creates a new Store instance.
-    pub fn new(store_path: &Path) -> Result<Store, StoreError> {
-        let mut store = Store {
+    /// Creates a new Store instance. The store is locked until the instance
+    /// is dropped, so that other processes can't modify the store file in
+    /// the meantime. The backend is picked based on the extension of the
+    /// store file.
+    pub fn new(store_path: &Path) -> Result<Store<'_>, StoreError> {
+        Store::with_backend(store_path, BackendKind::from_path(store_path))
+    }
+
+    /// Creates a new Store instance kept in the given kind of backend.
+    pub fn with_backend(
+        store_path: &Path,
+        kind: BackendKind,
+    ) -> Result<Store<'_>, StoreError> {
+        if store_path.is_dir() {
+            return Err(StoreError::App(StoreErrorKind::StorePathIsDirectory(
+                store_path.to_path_buf(),
+            )));
+        }
+        let lock = Store::lock(store_path)?;
+        let mut backend = kind.open(store_path)?;
+        let store = backend.load()?;
+        Ok(Store {
             store_path,
-            store: HashMap::new(),
-        };
-        store.load()?;
-        Ok(store)
+            max_length: Some(DEFAULT_MAX_LENGTH),
+            backups: DEFAULT_BACKUPS,
+            dry_run: false,
+            store,
+            settings: backend.settings(),
+            changed: HashSet::new(),
+            backend,
+            kind,
+            lock,
+        })
     }
 
-    /// Loads the store from the store file.
-    fn load(&mut self) -> Result<(), StoreError> {
-        // If the parent directory of the store file does not exist, create it.
-        if let Some(parent_dir) = self.store_path.parent() {
+    /// Sets whether the store file is saved as compact JSON instead of
+    /// pretty-printed JSON. Other backends ignore this.
+    pub fn set_compact(&mut self, compact: bool) {
+        self.backend.set_compact(compact);
+    }
+
+    /// Returns the settings of the store.
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// Makes the keys that only differ in case the same key from now on, and
+    /// saves the setting to the store file. Fails if there already are keys
+    /// that only differ in case, since they couldn't be told apart anymore.
+    pub fn set_case_insensitive_keys(
+        &mut self,
+        case_insensitive: bool,
+    ) -> Result<(), StoreError> {
+        if case_insensitive == self.settings.case_insensitive_keys {
+            return Ok(());
+        }
+        if case_insensitive {
+            let mut keys = self.store.keys().collect::<Vec<_>>();
+            keys.sort();
+            let mut seen = HashMap::new();
+            for key in keys {
+                if let Some(other) = seen.insert(key.to_lowercase(), key) {
+                    return Err(StoreError::App(
+                        StoreErrorKind::KeysDifferInCase(
+                            other.to_string(),
+                            key.to_string(),
+                        ),
+                    ));
+                }
+            }
+        }
+        self.settings.case_insensitive_keys = case_insensitive;
+        self.backend.set_settings(self.settings);
+        self.snapshot()?;
+        self.write()
+    }
+
+    /// Returns the key that the entry for the given key is kept under. That
+    /// is the given key itself, unless keys are case-insensitive and there is
+    /// an entry with the key in another case.
+    pub fn stored_key(&self, key: &str) -> String {
+        if self.settings.case_insensitive_keys {
+            self.key_ignoring_case(key)
+        } else {
+            key.to_string()
+        }
+    }
+
+    /// Returns the given key if there is an entry for it, or else the key of
+    /// an entry with the key in another case. If there are several, the
+    /// smallest one is picked. Returns the given key if there is neither.
+    fn key_ignoring_case(&self, key: &str) -> String {
+        if !self.store.contains_key(key) {
+            let lowercase = key.to_lowercase();
+            if let Some(stored) = self
+                .store
+                .keys()
+                .filter(|k| k.to_lowercase() == lowercase)
+                .min()
+            {
+                return stored.to_string();
+            }
+        }
+        key.to_string()
+    }
+
+    /// Locks the store at the given path, waiting for a while if another
+    /// process has locked it. A separate lock file is used, because the
+    /// store file is replaced whenever it is saved.
+    fn lock(store_path: &Path) -> Result<std::fs::File, StoreError> {
+        if let Some(parent_dir) = store_path.parent() {
             if !parent_dir.exists() {
-                std::fs::create_dir_all(parent_dir)?;
+                util::create_private_dir_all(parent_dir)?;
             }
         }
-        // If the store file does not exist, create it.
-        if !self.store_path.exists() {
-            std::fs::File::create(self.store_path)?;
+        let lock = util::open_private_file(
+            &append_to_file_name(store_path, ".lock"),
+            std::fs::OpenOptions::new().truncate(false),
+        )?;
+
+        let start = Instant::now();
+        loop {
+            match lock.try_lock() {
+                Ok(()) => return Ok(lock),
+                Err(std::fs::TryLockError::WouldBlock) => {
+                    if start.elapsed() > LOCK_TIMEOUT {
+                        return Err(StoreError::App(StoreErrorKind::Locked));
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+            }
         }
+    }
 
-        let content = std::fs::read_to_string(self.store_path)?;
-        if content.is_empty() {
-            // If the store file is empty, there is no point in going further
-            // to parse it, so return.
+    /// Takes a snapshot of the store file if anything changed, so that the
+    /// change can be undone, and saves the store to the store file, unless
+    /// this is a dry run.
+    fn save(&mut self) -> Result<(), StoreError> {
+        if self.dry_run {
             return Ok(());
         }
+        if !self.changed.is_empty() {
+            self.snapshot()?;
+        }
+        self.write()
+    }
 
-        let store = serde_json::from_str::<HashMap<String, String>>(&content)?;
-        for (k, v) in store.iter() {
-            self.store.insert(k.to_string(), v.to_string());
+    /// Saves the store to the store file, without taking a snapshot first.
+    fn write(&mut self) -> Result<(), StoreError> {
+        if self.dry_run {
+            return Ok(());
+        }
+        // Entries loaded from older store files have no creation time, so
+        // record them as created now.
+        let now = util::now();
+        for (key, entry) in self.store.iter_mut() {
+            if entry.created_at.is_none() {
+                entry.created_at = Some(now);
+                self.changed.insert(key.to_string());
+            }
         }
+        self.sync_aliases();
+        self.backend.save(&self.store, &self.changed)?;
+        self.changed.clear();
         Ok(())
     }
 
-    /// Saves the store to the store file.
-    fn save(&mut self) -> Result<(), StoreError> {
-        let content = serde_json::to_string(&self.store)?;
-        std::fs::write(self.store_path, content.as_bytes())?;
-        Ok(())
+    /// Keeps the descriptions of the aliases the same as the descriptions of
+    /// the entries they are aliases of. Aliases of entries that no longer
+    /// exist become entries on their own.
+    fn sync_aliases(&mut self) {
+        let aliases = self
+            .store
+            .iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v.alias_of.clone()?)))
+            .collect::<Vec<_>>();
+        for (key, alias_of) in aliases {
+            let target = self
+                .store
+                .get(&alias_of)
+                .filter(|_| alias_of != key)
+                .map(|e| (e.alias_of.clone(), e.description.clone()));
+            let entry = self.store.get_mut(&key).unwrap();
+            let before = entry.clone();
+            match target {
+                Some((None, description)) => entry.description = description,
+                // The entry is an alias of an alias, which can happen after
+                // renaming, so make it an alias of the final entry instead.
+                Some((Some(alias_of), description)) => {
+                    entry.alias_of = Some(alias_of);
+                    entry.description = description;
+                }
+                None => entry.alias_of = None,
+            }
+            if *entry != before {
+                self.changed.insert(key);
+            }
+        }
     }
 
     /// Adds/modifies an entry in the store and saves it to the store file.
-    pub fn set(&mut self, key: &str, value: &str) -> Result<(), StoreError> {
-        self.store.insert(key.to_string(), value.to_string());
+    /// Returns whether an existing entry was replaced.
+    pub fn set<E: Into<Entry>>(
+        &mut self,
+        key: &str,
+        entry: E,
+    ) -> Result<SetOutcome, StoreError> {
+        validate_key(key)?;
+        let key = self.stored_key(key);
+        let entry = entry.into();
+        self.check_length(&entry.description)?;
+        let outcome = match self.put(key, entry, util::now()) {
+            Some(old_description) => SetOutcome::Updated(old_description),
+            None => SetOutcome::Created,
+        };
         self.save()?;
-        Ok(())
+        Ok(outcome)
     }
 
-    /// Finds the matches for the given description.
-    pub fn find(&self, description: &str) -> Vec<[String; 2]> {
-        let matcher = SkimMatcherV2::default();
-        let mut matches = Vec::new();
-        for (k, v) in self.store.iter() {
-            let score = matcher.fuzzy_match(v, description);
-            if score.is_some() {
-                matches.push([k.to_string(), v.to_string()]);
+    /// Puts the entry under the given stored key, without saving the store.
+    /// An existing entry is changed in place: its description is replaced,
+    /// and so are its tags, category and note if the new entry has them,
+    /// while its pin, timestamps and counters are kept. Like
+    /// [`Store::update`], the description of an alias is set on the entry it
+    /// is an alias of. Returns the description of the existing entry, if
+    /// there was one.
+    fn put(
+        &mut self,
+        key: String,
+        mut entry: Entry,
+        now: u64,
+    ) -> Option<String> {
+        let existing = match self.store.get_mut(&key) {
+            Some(existing) => existing,
+            None => {
+                entry.created_at = Some(now);
+                self.store.insert(key.clone(), entry);
+                self.changed.insert(key);
+                return None;
+            }
+        };
+        let old_description = std::mem::replace(
+            &mut existing.description,
+            entry.description.clone(),
+        );
+        if !entry.tags.is_empty() {
+            existing.tags = entry.tags;
+        }
+        if entry.category.is_some() {
+            existing.category = entry.category;
+        }
+        if entry.note.is_some() {
+            existing.note = entry.note;
+        }
+        let alias_of = existing.alias_of.clone();
+        self.changed.insert(key);
+        if let Some(alias_of) = alias_of {
+            if let Some(target) = self.store.get_mut(&alias_of) {
+                target.description = entry.description;
+                self.changed.insert(alias_of);
             }
         }
-        matches
+        Some(old_description)
     }
 
-    /// Deletes an entry from the store and saves the store to the store file.
-    pub fn delete(&mut self, key: &str) -> Result<(), StoreError> {
-        if self.store.contains_key(key) {
-            self.store.remove(key);
-            self.save()?;
-            Ok(())
-        } else {
-            Err(StoreError::App(StoreErrorKind::KeyNotFound(
+    /// Returns an error if the description is longer than the limit.
+    fn check_length(&self, description: &str) -> Result<(), StoreError> {
+        let length = description.chars().count();
+        match self.max_length {
+            Some(max_length) if length > max_length => Err(StoreError::App(
+                StoreErrorKind::ValueTooLong(length, max_length),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds/modifies the entries in the store and saves it to the store file
+    /// once. If any of the keys or descriptions is invalid, nothing is
+    /// changed. Returns how many entries were added and how many were
+    /// replaced.
+    pub fn set_many(
+        &mut self,
+        entries: Vec<(String, Entry)>,
+    ) -> Result<Summary, StoreError> {
+        for (key, entry) in entries.iter() {
+            validate_key(key)?;
+            self.check_length(&entry.description)?;
+        }
+        let mut summary = Summary::default();
+        let now = util::now();
+        for (key, entry) in entries {
+            let key = self.stored_key(&key);
+            match self.put(key, entry, now) {
+                Some(_) => summary.updated += 1,
+                None => summary.added += 1,
+            }
+        }
+        self.save()?;
+        Ok(summary)
+    }
+
+    /// Modifies the description of an existing entry in the store and saves
+    /// it to the store file.
+    /// Modifying an alias modifies the entry it is an alias of.
+    pub fn update(&mut self, key: &str, value: &str) -> Result<(), StoreError> {
+        self.check_length(value)?;
+        let key = self.stored_key(key);
+        let key = match self.store.get(&key).and_then(|e| e.alias_of.clone()) {
+            Some(alias_of) => alias_of,
+            None => key,
+        };
+        match self.store.get_mut(&key) {
+            Some(entry) => {
+                entry.description = value.to_string();
+                self.changed.insert(key);
+                self.save()?;
+                Ok(())
+            }
+            None => Err(StoreError::App(StoreErrorKind::KeyNotFound(
                 key.to_string(),
-            )))
+            ))),
         }
     }
-}
 
-// Tests for CLI store.
-#[cfg(test)]
-mod tests {
-    extern crate tempfile;
+    /// Moves the value of an entry to a new key and saves the store to the
+    /// store file. Unless `overwrite` is set, this fails if the new key is
+    /// already in the store.
+    pub fn rename(
+        &mut self,
+        old_key: &str,
+        new_key: &str,
+        overwrite: bool,
+    ) -> Result<(), StoreError> {
+        let old_key = &self.stored_key(old_key);
+        if !self.store.contains_key(old_key) {
+            return Err(StoreError::App(StoreErrorKind::KeyNotFound(
+                old_key.to_string(),
+            )));
+        }
+        if old_key == new_key {
+            return Ok(());
+        }
+        validate_key(new_key)?;
+        // With case-insensitive keys, changing the case of a key is a rename
+        // onto itself, which replaces nothing.
+        let replaced = self.stored_key(new_key);
+        if replaced != *old_key {
+            if !overwrite && self.store.contains_key(&replaced) {
+                return Err(StoreError::App(StoreErrorKind::KeyExists(
+                    new_key.to_string(),
+                )));
+            }
+            if self.store.remove(&replaced).is_some() {
+                self.changed.insert(replaced);
+            }
+        }
+        let value = self.store.remove(old_key).unwrap();
+        self.store.insert(new_key.to_string(), value);
+        self.changed.insert(old_key.to_string());
+        self.changed.insert(new_key.to_string());
+        // Keep the aliases of the entry pointing to it.
+        for (k, v) in self.store.iter_mut() {
+            if v.alias_of.as_deref() == Some(old_key) {
+                v.alias_of = Some(new_key.to_string());
+                self.changed.insert(k.to_string());
+            }
+        }
+        self.save()?;
+        Ok(())
+    }
 
-    use super::*;
+    /// Adds an entry which is an alias of the entry with the target key, and
+    /// saves the store to the store file. If the target is an alias itself,
+    /// the entry becomes an alias of the entry the target is an alias of.
+    pub fn alias(&mut self, key: &str, target: &str) -> Result<(), StoreError> {
+        let target = &self.stored_key(target);
+        let entry = match self.store.get(target) {
+            Some(entry) => entry,
+            None => {
+                return Err(StoreError::App(StoreErrorKind::KeyNotFound(
+                    target.to_string(),
+                )))
+            }
+        };
+        validate_key(key)?;
+        if self.store.contains_key(&self.stored_key(key)) {
+            return Err(StoreError::App(StoreErrorKind::KeyExists(
+                key.to_string(),
+            )));
+        }
+        let alias = Entry {
+            description: entry.description.clone(),
+            created_at: Some(util::now()),
+            alias_of: Some(
+                entry.alias_of.as_deref().unwrap_or(target).to_string(),
+            ),
+            ..Default::default()
+        };
+        self.store.insert(key.to_string(), alias);
+        self.changed.insert(key.to_string());
+        self.save()?;
+        Ok(())
+    }
 
-    fn run_test(test: fn(Store)) {
-        // Setup
-        let store_file = tempfile::NamedTempFile::new().unwrap();
-        let store = Store::new(store_file.path()).unwrap();
-        // Run the test
-        test(store);
-        // Teardown
-        // ...
+    /// Records that the entries with the given keys were accessed now, counts
+    /// the use and saves the store to the store file.
+    pub fn mark_accessed(&mut self, keys: &[String]) -> Result<(), StoreError> {
+        let now = util::now();
+        for key in keys {
+            if let Some(entry) = self.store.get_mut(key) {
+                entry.last_accessed = Some(now);
+                entry.uses += 1;
+                self.changed.insert(key.to_string());
+            }
+        }
+        // Finding things is not worth undoing, so keep the snapshot of the
+        // last real change.
+        self.write()?;
+        Ok(())
     }
 
-    #[test]
-    fn test_load() {
-        run_test(|store| {
-            assert_eq!(store.store.len(), 0);
-        });
+    /// Records that the entry with the given key was accessed now, without
+    /// counting it as found, and saves the store to the store file.
+    pub fn touch(&mut self, key: &str) -> Result<(), StoreError> {
+        let key = self.stored_key(key);
+        match self.store.get_mut(&key) {
+            Some(entry) => {
+                entry.last_accessed = Some(util::now());
+                self.changed.insert(key);
+                // Like finding things, this is not worth undoing.
+                self.write()
+            }
+            None => Err(StoreError::App(StoreErrorKind::KeyNotFound(
+                key.to_string(),
+            ))),
+        }
     }
 
-    #[test]
-    fn test_set() {
-        run_test(|mut store| {
-            store.set("key", "value").unwrap();
-            assert_eq!(store.store.get("key").unwrap(), "value");
+    /// Pins or unpins the entry with the given key, and saves the store to
+    /// the store file. Pinned entries are found before the others.
+    pub fn set_pinned(
+        &mut self,
+        key: &str,
+        pinned: bool,
+    ) -> Result<(), StoreError> {
+        let key = self.stored_key(key);
+        match self.store.get_mut(&key) {
+            Some(entry) => {
+                entry.pinned = pinned;
+                self.changed.insert(key);
+                self.save()
+            }
+            None => Err(StoreError::App(StoreErrorKind::KeyNotFound(key))),
+        }
+    }
+
+    /// Returns the entry with the exact given key, or with the key in any
+    /// case if keys are case-insensitive.
+    pub fn entry(&self, key: &str) -> Option<&Entry> {
+        self.store.get(&self.stored_key(key))
+    }
+
+    /// Returns the value of the given key. If there is no entry with the exact
+    /// key, the key is matched case-insensitively instead.
+    pub fn get(&self, key: &str) -> Option<&String> {
+        let entry = self.store.get(key).or_else(|| {
+            self.store
+                .iter()
+                .filter(|(k, _)| k.to_lowercase() == key.to_lowercase())
+                // Pick the smallest key so that the result stays the same
+                // even if there are multiple case-insensitive matches.
+                .min_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, v)| v)
         });
+        entry.map(|entry| &entry.description)
     }
 
-    #[test]
-    fn test_find_single_result() {
-        run_test(|mut store| {
-            store.set("key", "value").unwrap();
-            let matches = store.find("value");
-            assert_eq!(matches.len(), 1);
-            assert_eq!(matches[0][0], "key".to_string());
-            assert_eq!(matches[0][1], "value".to_string());
+    /// Finds the matches for the given query, with the best matches first.
+    /// Both the keys and the descriptions are matched by default, and the
+    /// better of the two scores is used. Pinned entries come before the
+    /// others, and matches with the same score are sorted by their keys. An
+    /// empty query matches all the entries. Fails
+    /// only if the query is not a valid regex in regex mode.
+    pub fn find(
+        &self,
+        query: &str,
+        options: &FindOptions,
+    ) -> Result<Vec<Match>, StoreError> {
+        let ignore_case = options.case.ignores_case(query);
+        let matcher = if ignore_case {
+            SkimMatcherV2::default().ignore_case()
+        } else {
+            SkimMatcherV2::default().respect_case()
+        };
+        let words = query.split_whitespace().collect::<Vec<_>>();
+        let regex = match options.mode {
+            SearchMode::Fuzzy | SearchMode::Words => None,
+            SearchMode::Regex => Some(Regex::new(query).map_err(|e| {
+                StoreError::App(StoreErrorKind::InvalidRegex(e.to_string()))
+            })?),
+            // An escaped query is always a valid regex.
+            SearchMode::Substring => Some(
+                RegexBuilder::new(&regex::escape(query))
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .unwrap(),
+            ),
+        };
+        // Returns the score if the query matches the given text. The
+        // positions of the matched characters are only worked out for the
+        // matches that are returned.
+        let score_text = |text: &str| match (&regex, options.mode) {
+            (Some(regex), _) => regex.find(text).map(|_| 0),
+            (None, SearchMode::Words) => words
+                .iter()
+                .map(|word| matcher.fuzzy_match(text, word))
+                .sum(),
+            (None, _) => matcher.fuzzy_match(text, query),
+        };
+        // Returns the positions of the matched characters of the given text.
+        let match_indices = |text: &str| match (&regex, options.mode) {
+            (Some(regex), _) => regex
+                .find(text)
+                .map(|m| {
+                    text.char_indices()
+                        .enumerate()
+                        .filter(|(_, (start, _))| m.range().contains(start))
+                        .map(|(i, _)| i)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            (None, SearchMode::Words) => {
+                let mut indices = words
+                    .iter()
+                    .filter_map(|word| matcher.fuzzy_indices(text, word))
+                    .flat_map(|(_, indices)| indices)
+                    .collect::<Vec<_>>();
+                indices.sort_unstable();
+                indices.dedup();
+                indices
+            }
+            (None, _) => matcher
+                .fuzzy_indices(text, query)
+                .map(|(_, indices)| indices)
+                .unwrap_or_default(),
+        };
+        let scored = !query.is_empty()
+            && !options.invert
+            && matches!(options.mode, SearchMode::Fuzzy | SearchMode::Words);
+        let contains = options.contains.map(str::to_lowercase);
+        // The matches borrow from the store until they are sorted and
+        // limited, so that nothing is copied for the ones that are dropped.
+        let mut candidates: Vec<(&str, &Entry, Field, i64)> = Vec::new();
+        for (k, v) in self.store.iter() {
+            if let Some(tag) = options.tag {
+                if !v.tags.iter().any(|t| t == tag) {
+                    continue;
+                }
+            }
+            if options.category.is_some()
+                && v.category.as_deref() != options.category
+            {
+                continue;
+            }
+            if let Some(contains) = &contains {
+                if !v.description.to_lowercase().contains(contains.as_str()) {
+                    continue;
+                }
+            }
+            if query.is_empty() {
+                // Everything matches an empty query equally, so there is no
+                // need to ask the matcher.
+                if !options.invert {
+                    candidates.push((k, v, Field::Description, 0));
+                }
+                continue;
+            }
+            let tags = v.tags.join(" ");
+            let combined = if options.search_in == SearchIn::Combined {
+                v.combined(k)
+            } else {
+                String::new()
+            };
+            let best = [
+                (Field::Key, Some(k.as_str())),
+                (Field::Tags, Some(tags.as_str()).filter(|t| !t.is_empty())),
+                (Field::Note, v.note.as_deref()),
+                (Field::Description, Some(v.description.as_str())),
+                (Field::Combined, Some(combined.as_str())),
+            ]
+            .iter()
+            .filter(|(field, _)| options.search_in.includes(*field))
+            .filter_map(|(field, text)| {
+                score_text((*text)?).map(|score| (score, *field))
+            })
+            // On a tie, the last field (the description) is picked.
+            .max_by_key(|(score, _)| *score);
+            match best {
+                Some((score, field)) if !options.invert => {
+                    candidates.push((k, v, field, score))
+                }
+                None if options.invert => {
+                    candidates.push((k, v, Field::Description, 0))
+                }
+                _ => {}
+            }
+        }
+        if let Some(min_score) = options.min_score {
+            candidates.retain(|(_, _, _, score)| *score >= min_score);
+        }
+        candidates.sort_by(|(a_key, a, _, a_score), (b_key, b, _, b_score)| {
+            let by_score = b_score.cmp(a_score);
+            b.pinned
+                .cmp(&a.pinned)
+                .then(if options.by_frequency {
+                    b.uses.cmp(&a.uses).then(by_score)
+                } else {
+                    by_score
+                })
+                .then_with(|| a_key.cmp(b_key))
         });
+        if let Some(limit) = options.limit {
+            candidates.truncate(limit);
+        }
+        let matches = candidates
+            .into_iter()
+            .map(|(key, entry, field, score)| Match {
+                key: key.to_string(),
+                description: entry.description.clone(),
+                field,
+                score: Some(score).filter(|_| scored),
+                uses: entry.uses,
+                indices: if query.is_empty() || options.invert {
+                    Vec::new()
+                } else {
+                    match_indices(&entry.field_text(key, field))
+                },
+            })
+            .collect();
+        Ok(matches)
     }
 
-    #[test]
-    fn test_find_multiple_results() {
-        run_test(|mut store| {
-            store.set("key1", "value1").unwrap();
-            store.set("key2", "value2").unwrap();
+    /// Returns all the entries in the store, sorted by their keys.
+    pub fn list(&self) -> Vec<[String; 2]> {
+        let mut entries = self
+            .store
+            .iter()
+            .map(|(k, v)| [k.to_string(), v.description.clone()])
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
 
-            let matches = store.find("value");
-            assert_eq!(matches.len(), 2);
-            for [key, _] in matches {
-                // We don't know which key is added first, so check for both
-                // keys at the same time.
-                assert!(["key1", "key2"].contains(&key.as_str()));
+    /// Returns an iterator over the keys and descriptions of the entries,
+    /// borrowed from the store. Unlike [`Store::list`], nothing is copied,
+    /// but the order is unspecified.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.store
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.description.as_str()))
+    }
+
+    /// Returns up to `n` entries picked at random, in a random order. All
+    /// the entries are returned if there are fewer than `n`.
+    pub fn random(&self, n: usize) -> Vec<[String; 2]> {
+        let mut entries = self.list();
+        entries.shuffle(&mut rand::thread_rng());
+        entries.truncate(n);
+        entries
+    }
+
+    /// Returns the groups of keys whose entries have the same description,
+    /// ignoring the case of the descriptions if `ignore_case` is set. The
+    /// keys in each group and the groups themselves are sorted.
+    pub fn find_duplicates(&self, ignore_case: bool) -> Vec<Vec<String>> {
+        let mut groups = HashMap::new();
+        for (k, v) in self.store.iter() {
+            let description = if ignore_case {
+                v.description.to_lowercase()
+            } else {
+                v.description.clone()
+            };
+            groups
+                .entry(description)
+                .or_insert_with(Vec::new)
+                .push(k.to_string());
+        }
+        let mut groups = groups
+            .into_values()
+            .filter(|keys| keys.len() > 1)
+            .map(|mut keys| {
+                keys.sort();
+                keys
+            })
+            .collect::<Vec<_>>();
+        groups.sort();
+        groups
+    }
+
+    /// Returns the entries in the given namespace, sorted by their keys. The
+    /// namespace of a key is the part before its first `/`, so the entries
+    /// in the `git` namespace have keys like `git/rebase`.
+    pub fn list_namespace(&self, namespace: &str) -> Vec<[String; 2]> {
+        let prefix = format!("{}/", namespace);
+        self.list()
+            .into_iter()
+            .filter(|[k, _]| k.starts_with(&prefix))
+            .collect()
+    }
+
+    /// Returns the entries created at or after `since` and before `until`,
+    /// both in seconds since the Unix epoch, sorted by their keys. Entries
+    /// without a creation time are left out.
+    pub fn list_created(
+        &self,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Vec<[String; 2]> {
+        self.list()
+            .into_iter()
+            .filter(|[k, _]| match self.store[k].created_at {
+                Some(created_at) => {
+                    since.is_none_or(|since| created_at >= since)
+                        && until.is_none_or(|until| created_at < until)
+                }
+                None => false,
+            })
+            .collect()
+    }
+
+    /// Returns the distinct namespaces of the keys, sorted. Keys without a
+    /// `/` are not in any namespace.
+    pub fn namespaces(&self) -> Vec<String> {
+        let mut namespaces = self
+            .store
+            .keys()
+            .filter_map(|k| Some(k.split_once('/')?.0.to_string()))
+            .collect::<Vec<_>>();
+        namespaces.sort();
+        namespaces.dedup();
+        namespaces
+    }
+
+    /// Returns the number of entries in the store.
+    pub fn count(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Returns aggregate figures about the entries in the store. Ties are
+    /// broken by picking the smallest key.
+    pub fn stats(&self) -> Stats {
+        let mut entries = self.store.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(k, _)| *k);
+        let lengths = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.description.chars().count()))
+            .collect::<Vec<_>>();
+        let created = entries
+            .iter()
+            .filter_map(|(k, v)| v.created_at.map(|t| (k.to_string(), t)))
+            .collect::<Vec<_>>();
+        let total_length = lengths.iter().map(|(_, l)| l).sum::<usize>();
+        Stats {
+            count: entries.len(),
+            average_length: if entries.is_empty() {
+                0.0
+            } else {
+                total_length as f64 / entries.len() as f64
+            },
+            // `max_by_key` picks the last of the equal elements, so reverse
+            // the keys to pick the smallest one.
+            longest: lengths.into_iter().rev().max_by_key(|(_, l)| *l),
+            oldest: created.iter().min_by_key(|(_, t)| *t).cloned(),
+            newest: created.into_iter().rev().max_by_key(|(_, t)| *t),
+            tags: self.count_by_tag(),
+        }
+    }
+
+    /// Returns the number of entries with each tag, sorted by the tags.
+    pub fn count_by_tag(&self) -> Vec<(String, usize)> {
+        let mut counts = HashMap::new();
+        for entry in self.store.values() {
+            for tag in entry.tags.iter() {
+                *counts.entry(tag.to_string()).or_insert(0) += 1;
             }
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort();
+        counts
+    }
+
+    /// Returns the distinct categories of the entries and how many entries
+    /// are in each of them, sorted by the categories. Entries without a
+    /// category aren't counted.
+    pub fn count_by_category(&self) -> Vec<(String, usize)> {
+        let mut counts = HashMap::new();
+        for category in self.store.values().filter_map(|e| e.category.as_ref())
+        {
+            *counts.entry(category.to_string()).or_insert(0) += 1;
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort();
+        counts
+    }
+
+    /// Exports the keys and descriptions of the entries as CSV, with a
+    /// `key,description` header. The fields are quoted as per RFC 4180.
+    pub fn export_csv(&self) -> String {
+        self.export_csv_matching(|_| true)
+    }
+
+    /// Exports the entries with the keys that the predicate is true for, like
+    /// [`Store::export_csv`].
+    pub fn export_csv_matching<F: Fn(&str) -> bool>(
+        &self,
+        predicate: F,
+    ) -> String {
+        let mut csv = String::from("key,description\r\n");
+        for [k, v] in self.list().into_iter().filter(|[k, _]| predicate(k)) {
+            csv.push_str(&format!("{},{}\r\n", csv_field(&k), csv_field(&v)));
+        }
+        csv
+    }
+
+    /// Exports the keys and descriptions of the entries as a JSON object.
+    pub fn export_json(&self) -> Result<String, StoreError> {
+        self.export_json_matching(|_| true)
+    }
+
+    /// Exports the entries with the keys that the predicate is true for, like
+    /// [`Store::export_json`].
+    pub fn export_json_matching<F: Fn(&str) -> bool>(
+        &self,
+        predicate: F,
+    ) -> Result<String, StoreError> {
+        let entries = self
+            .store
+            .iter()
+            .filter(|(k, _)| predicate(k))
+            .map(|(k, v)| (k, &v.description))
+            .collect::<BTreeMap<_, _>>();
+        Ok(serde_json::to_string_pretty(&entries)?)
+    }
+
+    /// Merges the given entries into the store and saves the store to the
+    /// store file. Existing entries are kept as they are, unless `overwrite`
+    /// is set, in which case their descriptions are replaced. If
+    /// `ignore_case` is set, keys that only differ in case are the same
+    /// entry, as if keys were case-insensitive. Returns how many entries were
+    /// added, replaced and skipped, and how many of them were matched to an
+    /// entry in another case.
+    pub fn import(
+        &mut self,
+        entries: HashMap<String, String>,
+        overwrite: bool,
+        ignore_case: bool,
+    ) -> Result<Summary, StoreError> {
+        // Check all the entries first, so that nothing is imported if any of
+        // them is invalid.
+        let mut sorted = entries.iter().collect::<Vec<_>>();
+        sorted.sort();
+        for (key, description) in sorted {
+            validate_key(key)?;
+            self.check_length(description)?;
+        }
+        let mut summary = Summary::default();
+        let now = util::now();
+        // Go through the keys in order, so that keys which only differ in
+        // case always end up the same way when keys are case-insensitive.
+        let mut entries = entries.into_iter().collect::<Vec<_>>();
+        entries.sort();
+        for (key, description) in entries {
+            let stored = if ignore_case {
+                self.key_ignoring_case(&key)
+            } else {
+                self.stored_key(&key)
+            };
+            if stored != key {
+                summary.case_folded += 1;
+            }
+            let key = stored;
+            if self.store.contains_key(&key) && !overwrite {
+                summary.skipped += 1;
+                continue;
+            }
+            match self.put(key, Entry::new(&description), now) {
+                Some(_) => summary.updated += 1,
+                None => summary.added += 1,
+            }
+        }
+        self.save()?;
+        Ok(summary)
+    }
+
+    /// Copies the store file to the first backup, after moving the existing
+    /// backups one number up. The backup with the highest number is dropped
+    /// once there are as many backups as should be kept.
+    pub fn backup(&self) -> Result<(), StoreError> {
+        if self.dry_run || self.backups == 0 || !self.store_path.exists() {
+            return Ok(());
+        }
+        for n in (1..self.backups).rev() {
+            let path = numbered_backup_path(self.store_path, n);
+            if path.exists() {
+                std::fs::rename(
+                    path,
+                    numbered_backup_path(self.store_path, n + 1),
+                )?;
+            }
+        }
+        std::fs::copy(
+            self.store_path,
+            numbered_backup_path(self.store_path, 1),
+        )?;
+        Ok(())
+    }
+
+    /// Swaps the store file with the backup with the given number, so that
+    /// restoring the same backup again undoes the restore, and loads the
+    /// store from the restored file. A dry run only checks that the backup
+    /// exists.
+    pub fn restore(&mut self, n: usize) -> Result<(), StoreError> {
+        let backup_path = numbered_backup_path(self.store_path, n);
+        if n == 0 || !backup_path.exists() {
+            return Err(StoreError::App(StoreErrorKind::NoBackup(n)));
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        let temp_path = append_to_file_name(self.store_path, ".restore");
+        std::fs::rename(self.store_path, &temp_path)?;
+        std::fs::rename(&backup_path, self.store_path)?;
+        std::fs::rename(&temp_path, &backup_path)?;
+        // The backend may still be using the old store file, so open it
+        // again.
+        self.backend = self.kind.open(self.store_path)?;
+        self.store = self.backend.load()?;
+        self.settings = self.backend.settings();
+        self.changed.clear();
+        Ok(())
+    }
+
+    /// Copies the store file to the undo snapshot, replacing the previous
+    /// snapshot. Only the most recent change can be undone.
+    pub fn snapshot(&self) -> Result<(), StoreError> {
+        if self.dry_run || !self.store_path.exists() {
+            return Ok(());
+        }
+        std::fs::copy(self.store_path, undo_path(self.store_path))?;
+        Ok(())
+    }
+
+    /// Replaces the store file with the undo snapshot, undoing the last
+    /// change, and loads the store from it. The snapshot is used up, so the
+    /// same change can't be undone twice. A dry run only checks that there is
+    /// a snapshot.
+    pub fn undo(&mut self) -> Result<(), StoreError> {
+        let undo_path = undo_path(self.store_path);
+        if !undo_path.exists() {
+            return Err(StoreError::App(StoreErrorKind::NothingToUndo));
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        std::fs::rename(&undo_path, self.store_path)?;
+        // The backend may still be using the old store file, so open it
+        // again.
+        self.backend = self.kind.open(self.store_path)?;
+        self.store = self.backend.load()?;
+        self.settings = self.backend.settings();
+        self.changed.clear();
+        Ok(())
+    }
+
+    /// Deletes all the entries from the store and saves the store to the
+    /// store file. Returns how many entries were deleted.
+    pub fn clear(&mut self) -> Result<Summary, StoreError> {
+        let forgotten = self.store.len();
+        if forgotten > 0 {
+            self.backup()?;
+        }
+        self.changed.extend(self.store.drain().map(|(k, _)| k));
+        self.save()?;
+        Ok(Summary {
+            forgotten,
+            ..Default::default()
+        })
+    }
+
+    /// Deletes an entry from the store and saves the store to the store file.
+    pub fn delete(&mut self, key: &str) -> Result<(), StoreError> {
+        let stored_key = self.stored_key(key);
+        if self.store.contains_key(&stored_key) {
+            self.backup()?;
+            self.store.remove(&stored_key);
+            self.changed.insert(stored_key);
+            self.save()?;
+            Ok(())
+        } else {
+            Err(StoreError::App(StoreErrorKind::KeyNotFound(
+                key.to_string(),
+            )))
+        }
+    }
+
+    /// Deletes the entries with the given keys from the store and saves the
+    /// store to the store file once. Keys that are not in the store are
+    /// ignored.
+    pub fn delete_many(&mut self, keys: &[String]) -> Result<(), StoreError> {
+        if keys.iter().any(|k| self.store.contains_key(k)) {
+            self.backup()?;
+        }
+        for key in keys {
+            if self.store.remove(key).is_some() {
+                self.changed.insert(key.to_string());
+            }
+        }
+        self.save()?;
+        Ok(())
+    }
+
+    /// Deletes the entries with the keys that the predicate is true for and
+    /// saves the store to the store file once. Returns the keys of the
+    /// deleted entries, sorted.
+    pub fn delete_matching<F: Fn(&str) -> bool>(
+        &mut self,
+        predicate: F,
+    ) -> Result<Vec<String>, StoreError> {
+        let mut keys = self
+            .store
+            .keys()
+            .filter(|k| predicate(k))
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        self.delete_many(&keys)?;
+        Ok(keys)
+    }
+
+    /// Applies the operations in memory, in order, and saves the store to the
+    /// store file once at the end. Returns the outcome of each operation. An
+    /// operation that fails leaves the store as it was, without stopping the
+    /// others.
+    pub fn batch(
+        &mut self,
+        operations: &[Operation],
+    ) -> Result<Vec<Result<(), StoreError>>, StoreError> {
+        let forgets = operations.iter().any(|operation| {
+            matches!(operation, Operation::Forget(key) if self.store.contains_key(&self.stored_key(key)))
+        });
+        if forgets {
+            self.backup()?;
+        }
+        let now = util::now();
+        let mut outcomes = Vec::new();
+        for operation in operations {
+            let outcome = match operation {
+                Operation::Set(key, description) => validate_key(key)
+                    .and_then(|_| self.check_length(description))
+                    .map(|_| {
+                        let key = self.stored_key(key);
+                        self.put(key, Entry::new(description), now);
+                    }),
+                Operation::Forget(key) => {
+                    let stored_key = self.stored_key(key);
+                    match self.store.remove(&stored_key) {
+                        Some(_) => {
+                            self.changed.insert(stored_key);
+                            Ok(())
+                        }
+                        None => Err(StoreError::App(
+                            StoreErrorKind::KeyNotFound(key.to_string()),
+                        )),
+                    }
+                }
+            };
+            outcomes.push(outcome);
+        }
+        self.save()?;
+        Ok(outcomes)
+    }
+
+    /// Deletes the entries that were last accessed before the cutoff and
+    /// saves the store to the store file. The entries that were never
+    /// accessed are only deleted if `include_unknown` is set. Returns the
+    /// keys of the deleted entries, sorted.
+    pub fn prune(
+        &mut self,
+        cutoff: SystemTime,
+        include_unknown: bool,
+    ) -> Result<Vec<String>, StoreError> {
+        let cutoff = cutoff
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let last_accessed = self
+            .store
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.last_accessed))
+            .collect::<HashMap<_, _>>();
+        self.delete_matching(|key| match last_accessed[key] {
+            Some(last_accessed) => last_accessed < cutoff,
+            None => include_unknown,
+        })
+    }
+}
+
+impl Drop for Store<'_> {
+    fn drop(&mut self) {
+        let _ = self.lock.unlock();
+    }
+}
+
+/// Several stores that are searched and listed as one. The stores are only
+/// read, so that changes still go to a single store. Everything returned is
+/// paired with the index of the store it is from.
+pub struct MultiStore<'s, 'a> {
+    stores: Vec<&'s Store<'a>>,
+}
+
+impl<'s, 'a> MultiStore<'s, 'a> {
+    /// Combines the given stores.
+    pub fn new(stores: Vec<&'s Store<'a>>) -> MultiStore<'s, 'a> {
+        MultiStore { stores }
+    }
+
+    /// Returns the combined stores, in the order they were given.
+    pub fn stores(&self) -> &[&'s Store<'a>] {
+        &self.stores
+    }
+
+    /// Returns the number of entries in all the stores.
+    pub fn count(&self) -> usize {
+        self.stores.iter().map(|store| store.count()).sum()
+    }
+
+    /// Finds the entries of all the stores like [`Store::find`]. The matches
+    /// are sorted together the same way, with the matches of the earlier
+    /// stores first on a tie, and `options.limit` applies to all of them.
+    pub fn find(
+        &self,
+        query: &str,
+        options: &FindOptions,
+    ) -> Result<Vec<(usize, Match)>, StoreError> {
+        let mut matches = Vec::new();
+        for (i, store) in self.stores.iter().enumerate() {
+            matches.extend(
+                store.find(query, options)?.into_iter().map(|m| (i, m)),
+            );
+        }
+        let pinned = |i: usize, key: &str| {
+            self.stores[i].entry(key).is_some_and(|e| e.pinned)
+        };
+        matches.sort_by(|(a_store, a), (b_store, b)| {
+            let by_score = b.score.cmp(&a.score);
+            pinned(*b_store, &b.key)
+                .cmp(&pinned(*a_store, &a.key))
+                .then(if options.by_frequency {
+                    b.uses.cmp(&a.uses).then(by_score)
+                } else {
+                    by_score
+                })
+                .then_with(|| a.key.cmp(&b.key))
+                .then(a_store.cmp(b_store))
+        });
+        if let Some(limit) = options.limit {
+            matches.truncate(limit);
+        }
+        Ok(matches)
+    }
+
+    /// Returns the entries of all the stores, sorted by their keys and then
+    /// by the order of the stores.
+    pub fn list(&self) -> Vec<(usize, [String; 2])> {
+        self.combine(|store| store.list())
+    }
+
+    /// Returns the entries in the given namespace of all the stores, sorted
+    /// like [`MultiStore::list`].
+    pub fn list_namespace(&self, namespace: &str) -> Vec<(usize, [String; 2])> {
+        self.combine(|store| store.list_namespace(namespace))
+    }
+
+    /// Returns the entries of all the stores created in the given range,
+    /// like [`Store::list_created`], sorted like [`MultiStore::list`].
+    pub fn list_created(
+        &self,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Vec<(usize, [String; 2])> {
+        self.combine(|store| store.list_created(since, until))
+    }
+
+    /// Returns the entries listed by `list` for every store, sorted by their
+    /// keys and then by the order of the stores.
+    fn combine<F: Fn(&Store) -> Vec<[String; 2]>>(
+        &self,
+        list: F,
+    ) -> Vec<(usize, [String; 2])> {
+        let mut entries = self
+            .stores
+            .iter()
+            .enumerate()
+            .flat_map(|(i, store)| {
+                list(store).into_iter().map(move |entry| (i, entry))
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|(a_store, a), (b_store, b)| {
+            a[0].cmp(&b[0]).then(a_store.cmp(b_store))
+        });
+        entries
+    }
+}
+
+/// Returns the path that a corrupt store file is moved to by
+/// [`move_aside`].
+pub fn backup_path(store_path: &Path) -> PathBuf {
+    append_to_file_name(store_path, ".bak")
+}
+
+/// Moves a corrupt store file aside, so that the store can start over empty.
+/// Returns the path the store file was moved to.
+pub fn move_aside(store_path: &Path) -> Result<PathBuf, StoreError> {
+    let backup_path = backup_path(store_path);
+    std::fs::rename(store_path, &backup_path)?;
+    Ok(backup_path)
+}
+
+/// Compiles the pattern that keys are matched with: a regex, or a glob where
+/// `*` matches any text and `?` any character, which has to match the whole
+/// key.
+pub fn key_pattern(pattern: &str, is_regex: bool) -> Result<Regex, StoreError> {
+    let regex = if is_regex {
+        pattern.to_string()
+    } else {
+        let mut regex = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        regex
+    };
+    Regex::new(&regex).map_err(|e| {
+        StoreError::App(StoreErrorKind::InvalidRegex(e.to_string()))
+    })
+}
+
+/// Checks that the key can be shown on a line of its own and typed back in,
+/// i.e. it is not blank and has no line breaks.
+pub fn validate_key(key: &str) -> Result<(), StoreError> {
+    if key.trim().is_empty() || key.contains(['\n', '\r']) {
+        return Err(StoreError::App(StoreErrorKind::InvalidKey(
+            key.to_string(),
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the path to the backup of the store file with the given number,
+/// made by [`Store::backup`].
+pub fn numbered_backup_path(store_path: &Path, n: usize) -> PathBuf {
+    append_to_file_name(store_path, &format!(".{}", n))
+}
+
+/// Returns the path to the undo snapshot of the store file, made by
+/// [`Store::snapshot`]. It is named after the store file, with `undo` before
+/// the extension, like `store.undo.json`.
+pub fn undo_path(store_path: &Path) -> PathBuf {
+    let mut extension = std::ffi::OsString::from("undo");
+    if let Some(store_extension) = store_path.extension() {
+        extension.push(".");
+        extension.push(store_extension);
+    }
+    store_path.with_extension(extension)
+}
+
+/// Returns the given path with the suffix appended to its file name.
+pub fn append_to_file_name(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+/// Quotes the given CSV field if it contains any commas, quotes or line
+/// breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Parses the entries from a JSON object mapping the keys to the
+/// descriptions. Store files are accepted as well.
+pub fn parse_json(json: &str) -> Result<HashMap<String, String>, StoreError> {
+    let entries = serde_json::from_str::<HashMap<String, StoredEntry>>(json)?;
+    Ok(entries
+        .into_iter()
+        .map(|(k, v)| (k, Entry::from(v).description))
+        .collect())
+}
+
+/// Parses the entries from CSV with a `key,description` header, as written by
+/// [`Store::export_csv`].
+pub fn parse_csv(csv: &str) -> Result<HashMap<String, String>, StoreError> {
+    let invalid =
+        |reason: String| StoreError::App(StoreErrorKind::InvalidCsv(reason));
+
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    // An escaped quote.
+                    field.push(chars.next().unwrap());
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(invalid("unterminated quoted field".to_string()));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        // The last record is not followed by a line break.
+        record.push(field);
+        records.push(record);
+    }
+
+    let mut records = records.into_iter();
+    match records.next() {
+        Some(header) if header == ["key", "description"] => {}
+        _ => {
+            return Err(invalid(
+                "expected a `key,description` header".to_string(),
+            ))
+        }
+    }
+    let mut entries = HashMap::new();
+    for (i, record) in records.enumerate() {
+        match <[String; 2]>::try_from(record) {
+            Ok([key, description]) => {
+                entries.insert(key, description);
+            }
+            Err(record) => {
+                return Err(invalid(format!(
+                    "expected 2 fields in record {}, found {}",
+                    i + 1,
+                    record.len()
+                )))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Parses the operations of a batch, one per line: `set <key> <description>`
+/// or `forget <key>`. The words are separated by whitespace, and can be
+/// quoted with `"` or `'` to contain it, like `set "ls -l" "list files"`.
+/// The description is the rest of the line, so it doesn't need to be quoted.
+/// Blank lines and lines starting with `#` are skipped. Returns the line
+/// number of each operation along with it, or the reason it is malformed.
+pub fn parse_batch(batch: &str) -> Vec<(usize, Result<Operation, StoreError>)> {
+    batch
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| (n, parse_operation(line)))
+        .collect()
+}
+
+/// Parses an operation of a batch from its line.
+fn parse_operation(line: &str) -> Result<Operation, StoreError> {
+    let invalid = |reason: String| {
+        StoreError::App(StoreErrorKind::InvalidOperation(reason))
+    };
+    let words = split_words(line).map_err(invalid)?;
+    match words.split_first() {
+        Some((command, args)) if command == "set" && args.len() >= 2 => {
+            Ok(Operation::Set(args[0].clone(), args[1..].join(" ")))
+        }
+        Some((command, [key])) if command == "forget" => {
+            Ok(Operation::Forget(key.clone()))
+        }
+        Some((command, _)) if command == "set" => {
+            Err(invalid("expected `set <key> <description>`".to_string()))
+        }
+        Some((command, _)) if command == "forget" => {
+            Err(invalid("expected `forget <key>`".to_string()))
+        }
+        _ => Err(invalid(format!(
+            "unknown command in `{}`, expected `set` or `forget`",
+            line
+        ))),
+    }
+}
+
+/// Splits the line into words separated by whitespace. Quoted words can
+/// contain whitespace, and `\` escapes the next character in double quotes.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("unterminated quoted word".to_string()),
+            },
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quoted word".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+// Tests for CLI store.
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+
+    fn run_test(test: fn(Store)) {
+        // Setup
+        let store_file = tempfile::NamedTempFile::new().unwrap();
+        let store = Store::new(store_file.path()).unwrap();
+        // Run the test
+        test(store);
+        // Teardown
+        // ...
+    }
+
+    /// Drops the store and loads it again from the store file.
+    fn reload(store: Store) -> Store {
+        let store_path = store.store_path;
+        drop(store);
+        Store::new(store_path).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        run_test(|store| {
+            assert_eq!(store.store.len(), 0);
+        });
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        run_test(|mut store| {
+            for compact in [false, true] {
+                store.set_compact(compact);
+                store.set("key", "value").unwrap();
+                store.set("\"quoted\"", "multi\nline").unwrap();
+
+                let content =
+                    std::fs::read_to_string(store.store_path).unwrap();
+                assert_eq!(content.contains('\n'), !compact);
+
+                let entries = store.store.clone();
+                store = reload(store);
+                assert_eq!(store.store, entries);
+            }
+        });
+    }
+
+    #[test]
+    fn test_failed_save_keeps_store_file() {
+        run_test(|mut store| {
+            store.set("key", "value").unwrap();
+            let content = std::fs::read_to_string(store.store_path).unwrap();
+
+            // Simulate a save which fails before the store file is replaced,
+            // by putting a directory where the temporary file should go.
+            let temp_path = append_to_file_name(store.store_path, ".tmp");
+            std::fs::create_dir(&temp_path).unwrap();
+            assert!(store.set("key", "new value").is_err());
+            std::fs::remove_dir(&temp_path).unwrap();
+
+            assert_eq!(
+                std::fs::read_to_string(store.store_path).unwrap(),
+                content
+            );
+            let store = reload(store);
+            assert_eq!(store.store.get("key").unwrap().description, "value");
+        });
+    }
+
+    #[test]
+    fn test_save_replaces_truncated_temp_file() {
+        run_test(|mut store| {
+            // A truncated temporary file left behind by an interrupted save.
+            let temp_path = append_to_file_name(store.store_path, ".tmp");
+            std::fs::write(&temp_path, "{\"key\": \"val").unwrap();
+            store.set("key", "value").unwrap();
+
+            assert!(!temp_path.exists());
+            let store = reload(store);
+            assert_eq!(store.store.get("key").unwrap().description, "value");
+        });
+    }
+
+    #[test]
+    fn test_lock() {
+        run_test(|store| {
+            let start = Instant::now();
+            assert!(matches!(
+                Store::new(store.store_path),
+                Err(StoreError::App(StoreErrorKind::Locked))
+            ));
+            assert!(start.elapsed() > LOCK_TIMEOUT);
+
+            reload(store);
+        });
+    }
+
+    #[test]
+    fn test_set() {
+        run_test(|mut store| {
+            assert_eq!(store.set("key", "value").unwrap(), SetOutcome::Created);
+            assert_eq!(store.store.get("key").unwrap().description, "value");
+            assert_eq!(
+                store.set("key", "new value").unwrap(),
+                SetOutcome::Updated("value".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn test_update() {
+        run_test(|mut store| {
+            store.set("key", "value").unwrap();
+            store.update("key", "new value").unwrap();
+            assert_eq!(
+                store.store.get("key").unwrap().description,
+                "new value"
+            );
+        });
+    }
+
+    #[test]
+    fn test_update_missing_key() {
+        run_test(|mut store| {
+            let result = store.update("key", "value");
+            assert!(matches!(
+                result,
+                Err(StoreError::App(StoreErrorKind::KeyNotFound(key))) if key == "key"
+            ));
+            assert!(store.store.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_rename() {
+        run_test(|mut store| {
+            store.set("old", "value").unwrap();
+            store.rename("old", "new", false).unwrap();
+            assert!(!store.store.contains_key("old"));
+            assert_eq!(store.store.get("new").unwrap().description, "value");
+
+            assert!(matches!(
+                store.rename("old", "new", false),
+                Err(StoreError::App(StoreErrorKind::KeyNotFound(_)))
+            ));
+        });
+    }
+
+    #[test]
+    fn test_rename_existing_key() {
+        run_test(|mut store| {
+            store.set("old", "old value").unwrap();
+            store.set("new", "new value").unwrap();
+
+            assert!(matches!(
+                store.rename("old", "new", false),
+                Err(StoreError::App(StoreErrorKind::KeyExists(key))) if key == "new"
+            ));
+            assert_eq!(store.store.len(), 2);
+
+            store.rename("old", "new", true).unwrap();
+            assert_eq!(store.store.len(), 1);
+            assert_eq!(
+                store.store.get("new").unwrap().description,
+                "old value"
+            );
+        });
+    }
+
+    #[test]
+    fn test_clear() {
+        run_test(|mut store| {
+            store.set("key1", "value1").unwrap();
+            store.set("key2", "value2").unwrap();
+            assert_eq!(store.clear().unwrap().forgotten, 2);
+            assert_eq!(store.clear().unwrap(), Summary::default());
+
+            let store = reload(store);
+            assert_eq!(store.store.len(), 0);
+        });
+    }
+
+    #[test]
+    fn test_alias() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.alias("dir", "ls").unwrap();
+            store.alias("ll", "dir").unwrap();
+            assert!(matches!(
+                store.alias("ls", "dir"),
+                Err(StoreError::App(StoreErrorKind::KeyExists(_)))
+            ));
+            assert!(matches!(
+                store.alias("l", "missing"),
+                Err(StoreError::App(StoreErrorKind::KeyNotFound(_)))
+            ));
+            assert_eq!(
+                store.entry("ll").unwrap().alias_of.as_deref(),
+                Some("ls")
+            );
+
+            // Editing either the entry or an alias edits all of them.
+            store.update("ls", "list the files").unwrap();
+            assert_eq!(store.get("dir").unwrap(), "list the files");
+            store.update("dir", "list all the files").unwrap();
+            let mut store = reload(store);
+            assert_eq!(store.get("ls").unwrap(), "list all the files");
+            assert_eq!(store.get("ll").unwrap(), "list all the files");
+
+            store.rename("ls", "list", false).unwrap();
+            assert_eq!(
+                store.entry("dir").unwrap().alias_of.as_deref(),
+                Some("list")
+            );
+
+            // The aliases stay when the entry is forgotten.
+            store.delete("list").unwrap();
+            let store = reload(store);
+            assert_eq!(store.entry("dir").unwrap().alias_of, None);
+            assert_eq!(store.get("dir").unwrap(), "list all the files");
+        });
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        run_test(|mut store| {
+            let store_path = store.store_path;
+            let backup = |n| numbered_backup_path(store_path, n);
+            store.backups = 2;
+            store.set("key1", "value1").unwrap();
+            store.set("key2", "value2").unwrap();
+            store.set("key3", "value3").unwrap();
+            store.delete("key1").unwrap();
+            store.delete("key2").unwrap();
+            store.delete("key3").unwrap();
+            assert!(!backup(3).exists());
+
+            // The second backup is from before the second deletion.
+            store.restore(2).unwrap();
+            assert_eq!(store.count(), 2);
+            assert!(store.entry("key2").is_some());
+            let mut store = reload(store);
+            assert_eq!(store.count(), 2);
+
+            // Restoring the same backup again undoes the restore.
+            store.restore(2).unwrap();
+            assert_eq!(store.count(), 0);
+            assert!(matches!(
+                store.restore(3),
+                Err(StoreError::App(StoreErrorKind::NoBackup(3)))
+            ));
+
+            for n in 1..=2 {
+                std::fs::remove_file(backup(n)).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_set_invalid_key() {
+        run_test(|mut store| {
+            for key in ["", "   ", "\t", "two\nlines", "\r\n"] {
+                assert!(matches!(
+                    store.set(key, "value"),
+                    Err(StoreError::App(StoreErrorKind::InvalidKey(_)))
+                ));
+            }
+            assert_eq!(store.count(), 0);
+            // Whitespace around the key is fine, as long as there is more.
+            store.set(" key ", "value").unwrap();
+            assert!(matches!(
+                store.rename(" key ", " ", false),
+                Err(StoreError::App(StoreErrorKind::InvalidKey(_)))
+            ));
+            assert!(matches!(
+                store.alias("", " key "),
+                Err(StoreError::App(StoreErrorKind::InvalidKey(_)))
+            ));
+        });
+    }
+
+    #[test]
+    fn test_undo() {
+        run_test(|mut store| {
+            assert!(matches!(
+                store.undo(),
+                Err(StoreError::App(StoreErrorKind::NothingToUndo))
+            ));
+            store.set("key1", "value1").unwrap();
+            store.set("key2", "value2").unwrap();
+            // Finding things doesn't replace the snapshot.
+            store.mark_accessed(&["key1".to_string()]).unwrap();
+
+            store.undo().unwrap();
+            assert_eq!(store.list(), vec![["key1", "value1"]]);
+            let store = reload(store);
+            assert_eq!(store.list(), vec![["key1", "value1"]]);
+            assert!(!undo_path(store.store_path).exists());
+        });
+    }
+
+    #[test]
+    fn test_case_insensitive_keys() {
+        run_test(|mut store| {
+            store.set("Git", "version control").unwrap();
+            store.set("git", "the other one").unwrap();
+            store.delete("git").unwrap();
+            store.set_case_insensitive_keys(true).unwrap();
+
+            // The key keeps the case it was first given in.
+            store.set("git", "distributed version control").unwrap();
+            assert_eq!(
+                store.list(),
+                vec![["Git", "distributed version control"]]
+            );
+            let mut store = reload(store);
+            assert!(store.settings().case_insensitive_keys);
+            assert_eq!(
+                store.entry("GIT").map(|e| e.description.as_str()),
+                Some("distributed version control")
+            );
+            assert!(matches!(
+                store.alias("gIt", "Git"),
+                Err(StoreError::App(StoreErrorKind::KeyExists(_)))
+            ));
+            store.rename("git", "GIT", false).unwrap();
+            assert_eq!(
+                store.list(),
+                vec![["GIT", "distributed version control"]]
+            );
+            store.delete("git").unwrap();
+            assert_eq!(store.count(), 0);
+        });
+    }
+
+    #[test]
+    fn test_case_insensitive_keys_collision() {
+        run_test(|mut store| {
+            store.set("Git", "version control").unwrap();
+            store.set("git", "the other one").unwrap();
+            assert!(matches!(
+                store.set_case_insensitive_keys(true),
+                Err(StoreError::App(StoreErrorKind::KeysDifferInCase(a, b)))
+                    if a == "Git" && b == "git"
+            ));
+            assert!(!reload(store).settings().case_insensitive_keys);
+        });
+    }
+
+    #[test]
+    fn test_list_created() {
+        run_test(|mut store| {
+            store.set("key1", "value1").unwrap();
+            store.set("key2", "value2").unwrap();
+            store.set("key3", "value3").unwrap();
+            store.store.get_mut("key1").unwrap().created_at = Some(100);
+            store.store.get_mut("key2").unwrap().created_at = Some(200);
+            store.store.get_mut("key3").unwrap().created_at = None;
+
+            assert_eq!(
+                store.list_created(Some(100), None),
+                vec![["key1", "value1"], ["key2", "value2"]]
+            );
+            assert_eq!(
+                store.list_created(Some(150), Some(300)),
+                vec![["key2", "value2"]]
+            );
+            assert_eq!(
+                store.list_created(None, Some(200)),
+                vec![["key1", "value1"]]
+            );
+        });
+    }
+
+    #[test]
+    fn test_set_keeps_metadata() {
+        run_test(|mut store| {
+            let entry = Entry {
+                description: "list files".to_string(),
+                tags: vec!["shell".to_string()],
+                note: Some("see man ls".to_string()),
+                ..Default::default()
+            };
+            store.set("ls", entry).unwrap();
+            store.set_pinned("ls", true).unwrap();
+            store.mark_accessed(&["ls".to_string()]).unwrap();
+            store.mark_accessed(&["ls".to_string()]).unwrap();
+            let before = store.entry("ls").unwrap().clone();
+
+            assert_eq!(
+                store.set("ls", "list directory contents").unwrap(),
+                SetOutcome::Updated("list files".to_string())
+            );
+            let store = reload(store);
+            let after = store.entry("ls").unwrap();
+            assert_eq!(after.description, "list directory contents");
+            assert!(after.pinned);
+            assert_eq!(after.uses, 2);
+            assert_eq!(after.last_accessed, before.last_accessed);
+            assert_eq!(after.created_at, before.created_at);
+            assert_eq!(after.tags, ["shell"]);
+            assert_eq!(after.note.as_deref(), Some("see man ls"));
+        });
+    }
+
+    #[test]
+    fn test_set_aliased_entry() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.alias("dir", "ls").unwrap();
+            store.set("dir", "list directory contents").unwrap();
+            assert_eq!(
+                store.entry("dir").unwrap().alias_of.as_deref(),
+                Some("ls")
+            );
+            assert_eq!(store.get("ls").unwrap(), "list directory contents");
+        });
+    }
+
+    #[test]
+    fn test_set_many() {
+        run_test(|mut store| {
+            store.set("ls", "list").unwrap();
+            let entries = vec![
+                ("ls".to_string(), Entry::new("list files")),
+                ("cat".to_string(), Entry::new("print files")),
+            ];
+            assert_eq!(
+                store.set_many(entries).unwrap(),
+                Summary {
+                    added: 1,
+                    updated: 1,
+                    ..Default::default()
+                }
+            );
+            let store = reload(store);
+            assert_eq!(
+                store.list(),
+                vec![["cat", "print files"], ["ls", "list files"]]
+            );
+
+            // One invalid key rejects all of them.
+            let mut store = store;
+            let entries = vec![
+                ("tree".to_string(), Entry::new("show the tree")),
+                (" ".to_string(), Entry::new("nothing")),
+            ];
+            assert!(matches!(
+                store.set_many(entries),
+                Err(StoreError::App(StoreErrorKind::InvalidKey(_)))
+            ));
+            assert_eq!(store.count(), 2);
+        });
+    }
+
+    #[test]
+    fn test_max_length() {
+        run_test(|mut store| {
+            store.max_length = Some(5);
+            store.set("key1", "12345").unwrap();
+            assert!(matches!(
+                store.set("key2", "123456"),
+                Err(StoreError::App(StoreErrorKind::ValueTooLong(6, 5)))
+            ));
+            // The limit is in characters, not bytes.
+            store.set("key3", "ééééé").unwrap();
+            assert!(matches!(
+                store.update("key1", "123456"),
+                Err(StoreError::App(StoreErrorKind::ValueTooLong(6, 5)))
+            ));
+            assert_eq!(
+                store.list(),
+                vec![["key1", "12345"], ["key3", "ééééé"]]
+            );
+
+            store.max_length = None;
+            store.set("key2", "123456").unwrap();
+            assert_eq!(store.count(), 3);
+        });
+    }
+
+    #[test]
+    fn test_iter() {
+        run_test(|mut store| {
+            assert_eq!(store.iter().count(), 0);
+            store.set("key1", "value1").unwrap();
+            store.set("key2", "value2").unwrap();
+            let mut entries = store.iter().collect::<Vec<_>>();
+            entries.sort();
+            assert_eq!(entries, vec![("key1", "value1"), ("key2", "value2")]);
+        });
+    }
+
+    #[test]
+    fn test_random() {
+        run_test(|mut store| {
+            assert!(store.random(1).is_empty());
+            for i in 0..5 {
+                store.set(&format!("key{}", i), "value").unwrap();
+            }
+            let picked = store.random(3);
+            assert_eq!(picked.len(), 3);
+            assert!(picked.iter().all(|e| store.list().contains(e)));
+            let mut picked_keys =
+                picked.iter().map(|[k, _]| k).collect::<Vec<_>>();
+            picked_keys.dedup();
+            assert_eq!(picked_keys.len(), 3);
+
+            let mut all = store.random(10);
+            all.sort();
+            assert_eq!(all, store.list());
+        });
+    }
+
+    #[test]
+    fn test_pinned() {
+        run_test(|mut store| {
+            store.set("list", "list files").unwrap();
+            store.set("ls", "list").unwrap();
+            let keys = |store: &Store| {
+                store
+                    .find("list", &FindOptions::default())
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| m.key)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(keys(&store), vec!["list", "ls"]);
+
+            store.set_pinned("ls", true).unwrap();
+            let mut store = reload(store);
+            assert_eq!(keys(&store), vec!["ls", "list"]);
+
+            store.set_pinned("ls", false).unwrap();
+            assert_eq!(keys(&store), vec!["list", "ls"]);
+            assert!(matches!(
+                store.set_pinned("cat", true),
+                Err(StoreError::App(StoreErrorKind::KeyNotFound(_)))
+            ));
+        });
+    }
+
+    #[test]
+    fn test_undo_path() {
+        assert_eq!(
+            undo_path(Path::new("dir/store.json")),
+            Path::new("dir/store.undo.json")
+        );
+        assert_eq!(undo_path(Path::new("store")), Path::new("store.undo"));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        run_test(|mut store| {
+            store.set("key1", "value1").unwrap();
+            store.dry_run = true;
+            store.set("key2", "value2").unwrap();
+            store.delete("key1").unwrap();
+            assert_eq!(store.list(), vec![["key2", "value2"]]);
+            assert!(!numbered_backup_path(store.store_path, 1).exists());
+
+            let store = reload(store);
+            assert_eq!(store.list(), vec![["key1", "value1"]]);
+        });
+    }
+
+    #[test]
+    fn test_get() {
+        run_test(|mut store| {
+            store.set("Key", "upper").unwrap();
+            store.set("key", "lower").unwrap();
+            store.set("other", "value").unwrap();
+
+            assert_eq!(store.get("Key").unwrap(), "upper");
+            assert_eq!(store.get("key").unwrap(), "lower");
+            assert_eq!(store.get("OTHER").unwrap(), "value");
+            assert!(store.get("missing").is_none());
+        });
+    }
+
+    #[test]
+    fn test_load_entries_without_tags() {
+        run_test(|store| {
+            // Store files used to map the keys directly to the descriptions.
+            std::fs::write(
+                store.store_path,
+                r#"{"key1": "value1", "key2": {"description": "value2"}}"#,
+            )
+            .unwrap();
+
+            let store = reload(store);
+            assert_eq!(store.store.get("key1").unwrap(), &Entry::new("value1"));
+            assert_eq!(store.store.get("key2").unwrap(), &Entry::new("value2"));
+        });
+    }
+
+    #[test]
+    fn test_store_path_is_directory() {
+        let store_dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            Store::new(store_dir.path()),
+            Err(StoreError::App(StoreErrorKind::StorePathIsDirectory(_)))
+        ));
+    }
+
+    #[test]
+    fn test_load_corrupt_store() {
+        run_test(|store| {
+            let store_path = store.store_path;
+            drop(store);
+            std::fs::write(store_path, r#"{"key": "value",}"#).unwrap();
+
+            let error = Store::new(store_path).err().unwrap();
+            assert!(matches!(
+                error,
+                StoreError::App(StoreErrorKind::CorruptStore(_, _))
+            ));
+            assert!(error.to_string().contains(store_path.to_str().unwrap()));
+
+            assert_eq!(
+                move_aside(store_path).unwrap(),
+                backup_path(store_path)
+            );
+            let store = Store::new(store_path).unwrap();
+            assert_eq!(store.store.len(), 0);
+            // The temporary file is cleaned up on its own, but its backup is
+            // not.
+            std::fs::remove_file(backup_path(store_path)).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_load_store_not_utf8() {
+        run_test(|store| {
+            let store_path = store.store_path;
+            drop(store);
+            std::fs::write(store_path, b"{\"key\": \"va\xfflue\"}").unwrap();
+
+            let error = Store::new(store_path).err().unwrap();
+            assert!(matches!(
+                error,
+                StoreError::App(StoreErrorKind::NotUtf8(_, 11))
+            ));
+            assert!(error.to_string().contains("--repair"));
+        });
+    }
+
+    #[test]
+    fn test_timestamps() {
+        run_test(|store| {
+            std::fs::write(store.store_path, r#"{"old": "value"}"#).unwrap();
+            let mut store = reload(store);
+            assert!(store.store.get("old").unwrap().created_at.is_none());
+
+            store.set("new", "value").unwrap();
+            let new = store.store.get("new").unwrap();
+            assert!(new.created_at.is_some());
+            assert!(new.last_accessed.is_none());
+            // Entries without a creation time are backfilled on writes.
+            assert!(store.store.get("old").unwrap().created_at.is_some());
+
+            store.mark_accessed(&["new".to_string()]).unwrap();
+            let store = reload(store);
+            assert!(store.store.get("new").unwrap().last_accessed.is_some());
+            assert!(store.store.get("old").unwrap().last_accessed.is_none());
+            assert_eq!(store.store.get("new").unwrap().uses, 1);
+            assert_eq!(store.store.get("old").unwrap().uses, 0);
+        });
+    }
+
+    #[test]
+    fn test_prune() {
+        run_test(|mut store| {
+            for key in ["old", "new", "unknown"] {
+                store.set(key, "value").unwrap();
+            }
+            store.store.get_mut("old").unwrap().last_accessed = Some(1000);
+            store.touch("new").unwrap();
+            let cutoff = UNIX_EPOCH + Duration::from_secs(2000);
+
+            assert_eq!(store.prune(cutoff, false).unwrap(), vec!["old"]);
+            assert_eq!(store.list().len(), 2);
+            assert_eq!(store.prune(cutoff, true).unwrap(), vec!["unknown"]);
+            let store = reload(store);
+            assert_eq!(store.list(), vec![["new", "value"]]);
+        });
+    }
+
+    #[test]
+    fn test_touch() {
+        run_test(|mut store| {
+            store.set("key", "value").unwrap();
+            assert!(matches!(
+                store.touch("missing"),
+                Err(StoreError::App(StoreErrorKind::KeyNotFound(_)))
+            ));
+            store.touch("key").unwrap();
+            let store = reload(store);
+            let entry = store.entry("key").unwrap();
+            assert!(entry.last_accessed.is_some());
+            assert_eq!(entry.uses, 0);
+        });
+    }
+
+    #[test]
+    fn test_find_by_frequency() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.set("lsblk", "list block devices").unwrap();
+            store.mark_accessed(&["lsblk".to_string()]).unwrap();
+            let options = FindOptions {
+                search_in: SearchIn::Keys,
+                ..Default::default()
+            };
+            let keys = |options: &FindOptions| {
+                store
+                    .find("ls", options)
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| m.key)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(keys(&options), vec!["ls", "lsblk"]);
+            let options = FindOptions {
+                by_frequency: true,
+                ..options
+            };
+            assert_eq!(keys(&options), vec!["lsblk", "ls"]);
+        });
+    }
+
+    #[test]
+    fn test_find_with_tag() {
+        run_test(|mut store| {
+            let entry = Entry {
+                description: "value1".to_string(),
+                tags: vec!["tag".to_string()],
+                ..Default::default()
+            };
+            store.set("key1", entry).unwrap();
+            store.set("key2", "value2").unwrap();
+
+            let options = FindOptions {
+                tag: Some("tag"),
+                ..Default::default()
+            };
+            let matches = store.find("value", &options).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].key, "key1");
+            let options = FindOptions {
+                tag: Some("other"),
+                ..Default::default()
+            };
+            assert!(store.find("value", &options).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_find_single_result() {
+        run_test(|mut store| {
+            store.set("key", "value").unwrap();
+            let matches = store.find("value", &FindOptions::default()).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].key, "key".to_string());
+            assert_eq!(matches[0].description, "value".to_string());
+            assert_eq!(matches[0].indices, vec![0, 1, 2, 3, 4]);
+        });
+    }
+
+    #[test]
+    fn test_find_multiple_results() {
+        run_test(|mut store| {
+            store.set("key1", "value1").unwrap();
+            store.set("key2", "value2").unwrap();
+
+            let matches = store.find("value", &FindOptions::default()).unwrap();
+            // Matches with the same score are sorted by their keys.
+            let keys =
+                matches.iter().map(|m| m.key.as_str()).collect::<Vec<_>>();
+            assert_eq!(keys, ["key1", "key2"]);
+        })
+    }
+
+    #[test]
+    fn test_find_indices() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            let matches = store.find("lfi", &FindOptions::default()).unwrap();
+            assert_eq!(matches[0].field, Field::Description);
+            assert_eq!(matches[0].indices, vec![0, 5, 6]);
+        })
+    }
+
+    #[test]
+    fn test_find_keys_and_descriptions() {
+        run_test(|mut store| {
+            store.set("ls", "print directory contents").unwrap();
+            store.set("cat", "show a file").unwrap();
+
+            let find = |query, search_in| {
+                let options = FindOptions {
+                    search_in,
+                    ..Default::default()
+                };
+                store
+                    .find(query, &options)
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| (m.key, m.field))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                find("ls", SearchIn::Both),
+                vec![("ls".into(), Field::Key)]
+            );
+            assert_eq!(find("ls", SearchIn::Descriptions), vec![]);
+            assert_eq!(
+                find("ls", SearchIn::Keys),
+                vec![("ls".into(), Field::Key)]
+            );
+            // An entry matching on both fields is only found once.
+            assert_eq!(
+                find("t", SearchIn::Both),
+                vec![
+                    ("cat".into(), Field::Key),
+                    ("ls".into(), Field::Description)
+                ]
+            );
+        })
+    }
+
+    #[test]
+    fn test_find_fields() {
+        run_test(|mut store| {
+            let mut entry = Entry::new("print directory contents");
+            entry.note = Some("see also bat".to_string());
+            entry.tags = vec!["shell".to_string(), "files".to_string()];
+            store.set("ls", entry).unwrap();
+
+            let find = |query, fields: &[Field]| {
+                let options = FindOptions {
+                    search_in: SearchIn::fields(fields),
+                    ..Default::default()
+                };
+                store
+                    .find(query, &options)
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| (m.key, m.field, m.indices))
+                    .collect::<Vec<_>>()
+            };
+            // The note and the tags aren't searched by default.
+            assert_eq!(find("bat", &[Field::Key, Field::Description]), vec![]);
+            assert_eq!(
+                find("bat", &[Field::Note]),
+                vec![("ls".into(), Field::Note, vec![9, 10, 11])]
+            );
+            assert_eq!(
+                find("files", &[Field::Description, Field::Tags]),
+                vec![("ls".into(), Field::Tags, vec![6, 7, 8, 9, 10])]
+            );
+            assert_eq!(find("shell", &[Field::Key, Field::Note]), vec![]);
+            assert_eq!(find("ls", &[]), vec![]);
+        })
+    }
+
+    #[test]
+    fn test_find_combined() {
+        run_test(|mut store| {
+            let mut entry = Entry::new("undo the last commit");
+            entry.tags = vec!["vcs".to_string()];
+            store.set("git reset HEAD~", entry).unwrap();
+            store.set("gr", "reset the whole history").unwrap();
+
+            let options = FindOptions {
+                search_in: SearchIn::Combined,
+                ..Default::default()
+            };
+            let matches = store.find("git undo vcs", &options).unwrap();
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].key, "git reset HEAD~");
+            assert_eq!(matches[0].field, Field::Combined);
+            // Neither field matches the whole query on its own.
+            assert!(store
+                .find("git undo vcs", &FindOptions::default())
+                .unwrap()
+                .is_empty());
+        })
+    }
+
+    #[test]
+    fn test_multi_store() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.set("cat", "show a file").unwrap();
+            let other_file = tempfile::NamedTempFile::new().unwrap();
+            let mut other = Store::new(other_file.path()).unwrap();
+            other.set("ls", "list files here").unwrap();
+            other.set("exa", "list files nicely").unwrap();
+
+            let multi = MultiStore::new(vec![&store, &other]);
+            assert_eq!(multi.count(), 4);
+            assert_eq!(
+                multi.list(),
+                vec![
+                    (0, ["cat".to_string(), "show a file".to_string()]),
+                    (1, ["exa".to_string(), "list files nicely".to_string()]),
+                    (0, ["ls".to_string(), "list files".to_string()]),
+                    (1, ["ls".to_string(), "list files here".to_string()]),
+                ]
+            );
+            let options = FindOptions {
+                limit: Some(2),
+                ..Default::default()
+            };
+            let found = multi
+                .find("ls", &options)
+                .unwrap()
+                .into_iter()
+                .map(|(i, m)| (i, m.key))
+                .collect::<Vec<_>>();
+            // The limit applies to all the stores together.
+            assert_eq!(found, vec![(0, "ls".into()), (1, "ls".into())]);
+        })
+    }
+
+    #[test]
+    fn test_find_empty_query() {
+        run_test(|mut store| {
+            store.set("b", "second").unwrap();
+            store.set("a", "first").unwrap();
+            store.set("c", "third").unwrap();
+
+            let matches = store.find("", &FindOptions::default()).unwrap();
+            let keys =
+                matches.iter().map(|m| m.key.as_str()).collect::<Vec<_>>();
+            assert_eq!(keys, vec!["a", "b", "c"]);
+            assert!(matches
+                .iter()
+                .all(|m| m.score.is_none() && m.indices.is_empty()));
+        })
+    }
+
+    #[test]
+    fn test_find_sorted_by_score() {
+        run_test(|mut store| {
+            store.set("key1", "list all the files in a folder").unwrap();
+            store.set("key2", "list files").unwrap();
+            store.set("key3", "list files").unwrap();
+
+            let keys = store
+                .find("list files", &FindOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|m| m.key)
+                .collect::<Vec<_>>();
+            // The exact matches come first, sorted by their keys.
+            assert_eq!(keys, vec!["key2", "key3", "key1"]);
+        })
+    }
+
+    #[test]
+    fn test_find_regex() {
+        run_test(|mut store| {
+            store.set("git log", "show the commit history").unwrap();
+            store
+                .set("git status", "show the working tree status")
+                .unwrap();
+            store.set("tig", "a git interface").unwrap();
+
+            let options = FindOptions {
+                mode: SearchMode::Regex,
+                ..Default::default()
+            };
+            let matches = store.find("^git", &options).unwrap();
+            let keys = matches.iter().map(|m| &m.key).collect::<Vec<_>>();
+            assert_eq!(keys, vec!["git log", "git status"]);
+            assert_eq!(matches[0].field, Field::Key);
+            assert_eq!(matches[0].indices, vec![0, 1, 2]);
+            // All the matches of a regex are equal, so none of them has a
+            // score.
+            assert!(matches.iter().all(|m| m.score.is_none()));
+
+            assert!(matches!(
+                store.find("(git", &options),
+                Err(StoreError::App(StoreErrorKind::InvalidRegex(_)))
+            ));
+        })
+    }
+
+    #[test]
+    fn test_find_words() {
+        run_test(|mut store| {
+            store.set("docker ps", "list running containers").unwrap();
+            store.set("ls", "list files").unwrap();
+
+            let options = FindOptions {
+                mode: SearchMode::Words,
+                search_in: SearchIn::Descriptions,
+                ..Default::default()
+            };
+            // The words can match in any order, but all of them have to.
+            let matches = store.find("containers list", &options).unwrap();
+            let keys = matches.iter().map(|m| &m.key).collect::<Vec<_>>();
+            assert_eq!(keys, vec!["docker ps"]);
+            assert_eq!(
+                matches[0].indices,
+                (0..4).chain(13..23).collect::<Vec<_>>()
+            );
+            let fuzzy = FindOptions {
+                search_in: SearchIn::Descriptions,
+                ..Default::default()
+            };
+            assert!(store.find("containers list", &fuzzy).unwrap().is_empty());
+
+            let matches = store.find("list", &options).unwrap();
+            assert_eq!(matches.len(), 2);
+            let word_scores = store.find("list files", &options).unwrap();
+            assert_eq!(word_scores[0].key, "ls");
+            assert!(word_scores[0].score > matches[0].score);
+        })
+    }
+
+    #[test]
+    fn test_find_inverted() {
+        run_test(|mut store| {
+            store.set("git log", "show the commit history").unwrap();
+            store.set("ls", "list files").unwrap();
+            store.set("tree", "show the directory tree").unwrap();
+
+            let options = FindOptions {
+                invert: true,
+                ..Default::default()
+            };
+            let keys = |query: &str, options: &FindOptions| {
+                store
+                    .find(query, options)
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| m.key)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(keys("git", &options), vec!["ls", "tree"]);
+            assert!(keys("", &options).is_empty());
+
+            let options = FindOptions {
+                mode: SearchMode::Regex,
+                search_in: SearchIn::Descriptions,
+                invert: true,
+                ..Default::default()
+            };
+            assert_eq!(keys("^show", &options), vec!["ls"]);
+        })
+    }
+
+    #[test]
+    fn test_find_substring() {
+        run_test(|mut store| {
+            store.set("ls", "List files").unwrap();
+            store.set("find", "find files in a directory").unwrap();
+            store.set("lsblk", "list block devices").unwrap();
+
+            let options = FindOptions {
+                mode: SearchMode::Substring,
+                ..Default::default()
+            };
+            let matches = store.find("list", &options).unwrap();
+            let keys = matches.iter().map(|m| &m.key).collect::<Vec<_>>();
+            assert_eq!(keys, vec!["ls", "lsblk"]);
+            assert_eq!(matches[0].indices, vec![0, 1, 2, 3]);
+
+            // The query is not a regex.
+            assert!(store.find("l.st", &options).unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn test_find_with_min_score() {
+        run_test(|mut store| {
+            store.set("key1", "list all the files in a folder").unwrap();
+            store.set("key2", "list files").unwrap();
+
+            let matches =
+                store.find("list files", &FindOptions::default()).unwrap();
+            let best_score = matches[0].score.unwrap();
+            assert!(matches[1].score.unwrap() < best_score);
+
+            let options = FindOptions {
+                min_score: Some(best_score),
+                ..Default::default()
+            };
+            let keys = store
+                .find("list files", &options)
+                .unwrap()
+                .into_iter()
+                .map(|m| m.key)
+                .collect::<Vec<_>>();
+            assert_eq!(keys, vec!["key2"]);
+        })
+    }
+
+    #[test]
+    fn test_find_ignores_note() {
+        run_test(|mut store| {
+            let entry = Entry {
+                description: "list files".to_string(),
+                note: Some("see also exa".to_string()),
+                ..Default::default()
+            };
+            store.set("ls", entry).unwrap();
+            assert!(store
+                .find("exa", &FindOptions::default())
+                .unwrap()
+                .is_empty());
+            let store = reload(store);
+            assert_eq!(
+                store.entry("ls").unwrap().note.as_deref(),
+                Some("see also exa")
+            );
+        })
+    }
+
+    #[test]
+    fn test_find_with_contains() {
+        run_test(|mut store| {
+            store.set("ls", "List files").unwrap();
+            store.set("exa", "list files, with colors").unwrap();
+            store.set("lsd", "The other ls, with Colors").unwrap();
+            let options = FindOptions {
+                contains: Some("COLORS"),
+                ..Default::default()
+            };
+            let keys = store
+                .find("list files", &options)
+                .unwrap()
+                .into_iter()
+                .map(|m| m.key)
+                .collect::<Vec<_>>();
+            assert_eq!(keys, vec!["exa"]);
+        })
+    }
+
+    #[test]
+    fn test_find_case() {
+        run_test(|mut store| {
+            store.set("get", "fetch lowercase").unwrap();
+            store.set("GET", "fetch uppercase").unwrap();
+            let keys = |query: &str, mode: SearchMode, case: CaseMatching| {
+                let options = FindOptions {
+                    search_in: SearchIn::Keys,
+                    mode,
+                    case,
+                    ..Default::default()
+                };
+                let mut keys = store
+                    .find(query, &options)
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| m.key)
+                    .collect::<Vec<_>>();
+                keys.sort();
+                keys
+            };
+            for mode in [SearchMode::Fuzzy, SearchMode::Substring] {
+                assert_eq!(
+                    keys("GET", mode, CaseMatching::Ignore),
+                    vec!["GET", "get"]
+                );
+                assert_eq!(
+                    keys("GET", mode, CaseMatching::Respect),
+                    vec!["GET"]
+                );
+                assert_eq!(
+                    keys("get", mode, CaseMatching::Respect),
+                    vec!["get"]
+                );
+                assert_eq!(keys("GET", mode, CaseMatching::Smart), vec!["GET"]);
+                assert_eq!(
+                    keys("get", mode, CaseMatching::Smart),
+                    vec!["GET", "get"]
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn test_find_with_limit() {
+        run_test(|mut store| {
+            store.set("key1", "list all the files in a folder").unwrap();
+            store.set("key2", "list files").unwrap();
+            store.set("key3", "list the files").unwrap();
+
+            let all =
+                store.find("list files", &FindOptions::default()).unwrap();
+            let options = FindOptions {
+                limit: Some(2),
+                ..Default::default()
+            };
+            assert_eq!(store.find("list files", &options).unwrap(), all[..2]);
+        })
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.set("dir", "list files").unwrap();
+            store.set("ll", "List files").unwrap();
+            store.set("cat", "concatenate files").unwrap();
+
+            assert_eq!(store.find_duplicates(false), vec![vec!["dir", "ls"]]);
+            assert_eq!(
+                store.find_duplicates(true),
+                vec![vec!["dir", "ll", "ls"]]
+            );
+        })
+    }
+
+    #[test]
+    fn test_delete_many() {
+        run_test(|mut store| {
+            store.set("key1", "value1").unwrap();
+            store.set("key2", "value2").unwrap();
+            store.set("key3", "value3").unwrap();
+            store
+                .delete_many(&["key1".to_string(), "missing".to_string()])
+                .unwrap();
+
+            let store = reload(store);
+            assert_eq!(store.list().len(), 2);
+            assert!(store.entry("key1").is_none());
+        })
+    }
+
+    #[test]
+    fn test_stats() {
+        run_test(|mut store| {
+            assert_eq!(
+                store.stats(),
+                Stats {
+                    count: 0,
+                    average_length: 0.0,
+                    longest: None,
+                    oldest: None,
+                    newest: None,
+                    tags: Vec::new(),
+                }
+            );
+
+            let entry = |description: &str, created_at, tags: &[&str]| Entry {
+                description: description.to_string(),
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                created_at: Some(created_at),
+                ..Default::default()
+            };
+            store
+                .store
+                .insert("b".to_string(), entry("four", 20, &["x"]));
+            store
+                .store
+                .insert("a".to_string(), entry("four", 10, &["x"]));
+            store.store.insert("c".to_string(), entry("ab", 20, &[]));
+            store.store.insert("d".to_string(), Entry::new(""));
+
+            assert_eq!(
+                store.stats(),
+                Stats {
+                    count: 4,
+                    average_length: 2.5,
+                    longest: Some(("a".to_string(), 4)),
+                    oldest: Some(("a".to_string(), 10)),
+                    newest: Some(("b".to_string(), 20)),
+                    tags: vec![("x".to_string(), 2)],
+                }
+            );
+        })
+    }
+
+    #[test]
+    fn test_count_by_tag() {
+        run_test(|mut store| {
+            let tagged = |tags: &[&str]| Entry {
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Default::default()
+            };
+            store.set("key1", tagged(&["b", "a"])).unwrap();
+            store.set("key2", tagged(&["b"])).unwrap();
+            store.set("key3", tagged(&[])).unwrap();
+
+            assert_eq!(store.count(), 3);
+            assert_eq!(
+                store.count_by_tag(),
+                vec![("a".to_string(), 1), ("b".to_string(), 2)]
+            );
+        });
+    }
+
+    #[test]
+    fn test_categories() {
+        run_test(|mut store| {
+            let categorized =
+                |description: &str, category: Option<&str>| Entry {
+                    description: description.to_string(),
+                    category: category.map(str::to_string),
+                    ..Default::default()
+                };
+            store
+                .set("ls", categorized("list files", Some("shell")))
+                .unwrap();
+            store
+                .set("exa", categorized("list files", Some("shell")))
+                .unwrap();
+            store
+                .set("git ls-files", categorized("list files", Some("git")))
+                .unwrap();
+            store.set("dir", categorized("list files", None)).unwrap();
+
+            assert_eq!(
+                store.count_by_category(),
+                vec![("git".to_string(), 1), ("shell".to_string(), 2)]
+            );
+            let options = FindOptions {
+                category: Some("shell"),
+                ..Default::default()
+            };
+            let keys = store
+                .find("list files", &options)
+                .unwrap()
+                .into_iter()
+                .map(|m| m.key)
+                .collect::<Vec<_>>();
+            assert_eq!(keys, vec!["exa", "ls"]);
+        });
+    }
+
+    #[test]
+    fn test_export_csv() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.set("a,b", "say \"hi\"\nthen leave").unwrap();
+
+            assert_eq!(
+                store.export_csv(),
+                "key,description\r\n\
+                \"a,b\",\"say \"\"hi\"\"\nthen leave\"\r\n\
+                ls,list files\r\n"
+            );
+        });
+    }
+
+    #[test]
+    fn test_export_json() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.set("cat", "print files").unwrap();
+
+            let exported = serde_json::from_str::<HashMap<String, String>>(
+                &store.export_json().unwrap(),
+            )
+            .unwrap();
+            assert_eq!(exported.len(), 2);
+            assert_eq!(exported.get("ls").unwrap(), "list files");
+            assert_eq!(exported.get("cat").unwrap(), "print files");
+        });
+    }
+
+    #[test]
+    fn test_export_matching() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.set("cat", "print files").unwrap();
+
+            assert_eq!(
+                store.export_csv_matching(|k| k == "ls"),
+                "key,description\r\nls,list files\r\n"
+            );
+            let exported = serde_json::from_str::<HashMap<String, String>>(
+                &store.export_json_matching(|k| k != "ls").unwrap(),
+            )
+            .unwrap();
+            assert_eq!(
+                exported,
+                HashMap::from([("cat".to_string(), "print files".to_string())])
+            );
+        });
+    }
+
+    #[test]
+    fn test_import() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            let entries = HashMap::from([
+                ("ls".to_string(), "list directory contents".to_string()),
+                ("cat".to_string(), "print files".to_string()),
+            ]);
+
+            assert_eq!(
+                store.import(entries.clone(), false, false).unwrap(),
+                Summary {
+                    added: 1,
+                    skipped: 1,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(store.get("ls").unwrap(), "list files");
+            assert_eq!(store.get("cat").unwrap(), "print files");
+
+            assert_eq!(
+                store.import(entries, true, false).unwrap(),
+                Summary {
+                    updated: 2,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(store.get("ls").unwrap(), "list directory contents");
+        });
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(Summary::default().to_string(), "nothing changed.");
+        let summary = Summary {
+            added: 3,
+            updated: 1,
+            skipped: 2,
+            ..Default::default()
+        };
+        assert_eq!(summary.to_string(), "3 added, 1 updated, 2 skipped.");
+        let summary = Summary {
+            skipped: 1,
+            forgotten: 4,
+            case_folded: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.to_string(),
+            "1 skipped, 4 forgotten, 1 matched in another case."
+        );
+    }
+
+    #[test]
+    fn test_import_max_length() {
+        run_test(|mut store| {
+            store.max_length = Some(5);
+            let entries = HashMap::from([
+                ("ls".to_string(), "list".to_string()),
+                ("cat".to_string(), "print files".to_string()),
+            ]);
+            assert!(matches!(
+                store.import(entries, false, false),
+                Err(StoreError::App(StoreErrorKind::ValueTooLong(11, 5)))
+            ));
+            // Nothing is imported if any of the entries is too long.
+            assert_eq!(store.count(), 0);
+        });
+    }
+
+    #[test]
+    fn test_import_aliased_entry() {
+        run_test(|mut store| {
+            store.set("git", "old").unwrap();
+            store.alias("g", "git").unwrap();
+            let entries = HashMap::from([("g".to_string(), "new".to_string())]);
+            assert_eq!(
+                store.import(entries, true, false).unwrap(),
+                Summary {
+                    updated: 1,
+                    ..Default::default()
+                }
+            );
+            let store = reload(store);
+            assert_eq!(store.get("git").unwrap(), "new");
+            assert_eq!(store.get("g").unwrap(), "new");
+        });
+    }
+
+    #[test]
+    fn test_import_ignoring_case() {
+        run_test(|mut store| {
+            store.set("Git", "version control").unwrap();
+            let entries = HashMap::from([
+                ("GIT".to_string(), "track changes".to_string()),
+                ("ls".to_string(), "list files".to_string()),
+                ("LS".to_string(), "list files again".to_string()),
+            ]);
+
+            // Without the flag, every key is its own entry.
+            let mut case_sensitive = entries.clone();
+            case_sensitive.remove("LS");
+            assert_eq!(
+                store.import(case_sensitive, false, false).unwrap(),
+                Summary {
+                    added: 2,
+                    ..Default::default()
+                }
+            );
+            store
+                .delete_many(&["GIT".to_string(), "ls".to_string()])
+                .unwrap();
+
+            // "LS" comes before "ls", so it is the one that is added.
+            assert_eq!(
+                store.import(entries.clone(), false, true).unwrap(),
+                Summary {
+                    added: 1,
+                    skipped: 2,
+                    case_folded: 2,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(store.get("Git").unwrap(), "version control");
+            assert_eq!(store.get("LS").unwrap(), "list files again");
+            assert!(store.entry("ls").is_none());
+
+            assert_eq!(
+                store.import(entries, true, true).unwrap(),
+                Summary {
+                    updated: 3,
+                    case_folded: 2,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(store.get("Git").unwrap(), "track changes");
+            assert_eq!(store.get("LS").unwrap(), "list files");
+            assert_eq!(store.count(), 2);
+        });
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        run_test(|mut store| {
+            store.set("ls", "list files").unwrap();
+            store.set("a,b", "say \"hi\"\r\nthen leave").unwrap();
+            store.set("empty", "").unwrap();
+
+            let entries = parse_csv(&store.export_csv()).unwrap();
+            let expected = store
+                .list()
+                .into_iter()
+                .map(|[k, v]| (k, v))
+                .collect::<HashMap<_, _>>();
+            assert_eq!(entries, expected);
+        });
+    }
+
+    #[test]
+    fn test_parse_invalid_csv() {
+        assert!(parse_csv("ls,list files\n").is_err());
+        assert!(parse_csv("key,description\nls\n").is_err());
+        assert!(parse_csv("key,description\n\"ls,list files\n").is_err());
+        assert!(matches!(
+            parse_json("{\"ls\": 1}"),
+            Err(StoreError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let batch = "# A comment\n\
+            set ls list files\n\
+            \n\
+            set \"ls -l\" 'list files, long'\n\
+            forget \"say \\\"hi\\\"\"\n\
+            forget\n\
+            sit ls\n\
+            set \"ls";
+        let operations = parse_batch(batch);
+        assert_eq!(
+            operations[..3]
+                .iter()
+                .map(|(n, operation)| (*n, operation.as_ref().unwrap().clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    2,
+                    Operation::Set("ls".to_string(), "list files".to_string())
+                ),
+                (
+                    4,
+                    Operation::Set(
+                        "ls -l".to_string(),
+                        "list files, long".to_string()
+                    )
+                ),
+                (5, Operation::Forget("say \"hi\"".to_string())),
+            ]
+        );
+        assert_eq!(operations.len(), 6);
+        for (_, operation) in &operations[3..] {
+            assert!(matches!(
+                operation,
+                Err(StoreError::App(StoreErrorKind::InvalidOperation(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_delete_matching() {
+        run_test(|mut store| {
+            for key in ["docker ps", "docker rm", "git log", "dockerd"] {
+                store.set(key, "value").unwrap();
+            }
+            let pattern = key_pattern("docker *", false).unwrap();
+            let keys = store.delete_matching(|k| pattern.is_match(k)).unwrap();
+            assert_eq!(keys, vec!["docker ps", "docker rm"]);
+            let store = reload(store);
+            assert_eq!(
+                store.list().into_iter().map(|[k, _]| k).collect::<Vec<_>>(),
+                vec!["dockerd", "git log"]
+            );
+        });
+    }
+
+    #[test]
+    fn test_key_pattern() {
+        let glob = key_pattern("git ?og*", false).unwrap();
+        assert!(glob.is_match("git log --oneline"));
+        assert!(!glob.is_match("my git log"));
+        assert!(key_pattern("(", false).unwrap().is_match("("));
+        assert!(key_pattern("^git", true).unwrap().is_match("git log"));
+        assert!(matches!(
+            key_pattern("(", true),
+            Err(StoreError::App(StoreErrorKind::InvalidRegex(_)))
+        ));
+    }
+
+    #[test]
+    fn test_batch() {
+        run_test(|mut store| {
+            store.set("old", "value").unwrap();
+            let operations = [
+                Operation::Set("new".to_string(), "value".to_string()),
+                Operation::Forget("old".to_string()),
+                Operation::Forget("missing".to_string()),
+                Operation::Set(" ".to_string(), "value".to_string()),
+            ];
+            let outcomes = store.batch(&operations).unwrap();
+            assert!(outcomes[0].is_ok() && outcomes[1].is_ok());
+            assert!(outcomes[2].is_err() && outcomes[3].is_err());
+
+            let store = reload(store);
+            assert_eq!(store.list(), vec![["new", "value"]]);
+        });
+    }
+
+    #[test]
+    fn test_batch_keeps_metadata() {
+        run_test(|mut store| {
+            store.set("ls", "list").unwrap();
+            store.set_pinned("ls", true).unwrap();
+            store.mark_accessed(&["ls".to_string()]).unwrap();
+            let operations =
+                [Operation::Set("ls".to_string(), "list files".to_string())];
+            store.batch(&operations).unwrap();
+
+            let store = reload(store);
+            let entry = store.entry("ls").unwrap();
+            assert_eq!(entry.description, "list files");
+            assert!(entry.pinned);
+            assert_eq!(entry.uses, 1);
+        });
+    }
+
+    #[test]
+    fn test_batch_max_length() {
+        run_test(|mut store| {
+            store.max_length = Some(5);
+            let operations = [
+                Operation::Set("short".to_string(), "12345".to_string()),
+                Operation::Set("long".to_string(), "123456".to_string()),
+            ];
+            let outcomes = store.batch(&operations).unwrap();
+            assert!(outcomes[0].is_ok());
+            assert!(matches!(
+                outcomes[1],
+                Err(StoreError::App(StoreErrorKind::ValueTooLong(6, 5)))
+            ));
+            assert_eq!(store.list(), vec![["short", "12345"]]);
+        });
+    }
+
+    #[test]
+    fn test_namespaces() {
+        run_test(|mut store| {
+            store.set("git/rebase", "reapply commits").unwrap();
+            store.set("git/log", "show the commit history").unwrap();
+            store.set("docker/prune", "remove unused data").unwrap();
+            store.set("gitk", "browse the commit history").unwrap();
+
+            assert_eq!(
+                store.list_namespace("git"),
+                vec![
+                    [
+                        "git/log".to_string(),
+                        "show the commit history".to_string()
+                    ],
+                    ["git/rebase".to_string(), "reapply commits".to_string()],
+                ]
+            );
+            assert_eq!(store.namespaces(), vec!["docker", "git"]);
+        })
+    }
+
+    #[test]
+    fn test_list_sorted_by_key() {
+        run_test(|mut store| {
+            store.set("b", "first").unwrap();
+            store.set("c", "second").unwrap();
+            store.set("a", "third").unwrap();
+
+            let keys =
+                store.list().into_iter().map(|[k, _]| k).collect::<Vec<_>>();
+            assert_eq!(keys, vec!["a", "b", "c"]);
         })
     }
 }
@@ -0,0 +1,316 @@
+// Copyright 2022 Pranav Karawale
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, BufRead, Read, Write};
+
+use what_was_that::util;
+
+/// Reads the standard input to the end, without the trailing line break.
+pub fn read_stdin() -> io::Result<String> {
+    let mut input = String::new();
+    io::stdin().lock().read_to_string(&mut input)?;
+    Ok(trim_line_break(input))
+}
+
+/// Reads the file at the given path, without the trailing line break.
+pub fn read_file(path: &str) -> io::Result<String> {
+    Ok(trim_line_break(std::fs::read_to_string(path)?))
+}
+
+/// Removes one trailing line break, which editors put at the end of files
+/// and `echo` at the end of its output.
+fn trim_line_break(mut input: String) -> String {
+    if input.ends_with('\n') {
+        input.pop();
+        if input.ends_with('\r') {
+            input.pop();
+        }
+    }
+    input
+}
+
+/// Asks the user to confirm the given question on the terminal, and returns
+/// whether they answered yes. Returns `None` if there is no terminal to ask
+/// on.
+pub fn confirm(question: &str) -> Option<bool> {
+    if !util::stdin_is_tty() {
+        return None;
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().ok()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok()?;
+    Some(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Status codes that the program exits with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitCode {
+    /// Everything went fine.
+    Success = 0,
+    /// Something went wrong, e.g. the store could not be read.
+    Error = 1,
+    /// `find` did not find anything.
+    NoMatches = 2,
+    /// A destructive command was refused, because it could not be confirmed.
+    Unconfirmed = 3,
+    /// `find` had nothing to look through, because the store is empty.
+    EmptyStore = 4,
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        std::process::ExitCode::from(code as u8)
+    }
+}
+
+/// How the things that are found or listed are printed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Format {
+    /// As `key -> value`, noting what the aliases are aliases of.
+    Default,
+    /// With a template, like `{value} ({key})`.
+    Template(Vec<Piece>),
+}
+
+/// A piece of a [`Format::Template`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Piece {
+    /// Text printed as it is.
+    Text(String),
+    /// The `{key}` placeholder, for the name of the thing.
+    Key,
+    /// The `{value}` placeholder, for the description of the thing.
+    Value,
+    /// The `{score}` placeholder, for how well the thing matched. Empty when
+    /// listing the things.
+    Score,
+}
+
+impl Format {
+    /// Parses the name of a preset (`default`, `keys`, `values` or `tsv`) or a
+    /// template with the `{key}`, `{value}` and `{score}` placeholders. `{{`
+    /// and `}}` stand for `{` and `}`.
+    pub fn parse(format: &str) -> Result<Format, String> {
+        let template = match format {
+            "default" => return Ok(Format::Default),
+            "keys" => "{key}",
+            "values" => "{value}",
+            "tsv" => "{key}\t{value}",
+            template => template,
+        };
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| {
+                        "unclosed placeholder, use {{ for a literal {"
+                            .to_string()
+                    })?;
+                    let piece = match &rest[..end] {
+                        "key" => Piece::Key,
+                        "value" => Piece::Value,
+                        "score" => Piece::Score,
+                        name => {
+                            return Err(format!(
+                                "unknown placeholder {{{}}}, expected {{key}}, \
+                                {{value}} or {{score}}",
+                                name
+                            ))
+                        }
+                    };
+                    chars = rest[end + 1..].chars();
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(piece);
+                }
+                '}' => {
+                    return Err(
+                        "unmatched }, use }} for a literal }".to_string()
+                    )
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Format::Template(pieces))
+    }
+
+    /// Renders a thing as a line, without the line break.
+    pub fn render(
+        &self,
+        key: &str,
+        value: &str,
+        score: Option<i64>,
+        alias_of: Option<&str>,
+    ) -> String {
+        let pieces = match self {
+            Format::Default => {
+                return match alias_of {
+                    Some(alias_of) => {
+                        format!("{} -> {} (alias of {})", key, value, alias_of)
+                    }
+                    None => format!("{} -> {}", key, value),
+                }
+            }
+            Format::Template(pieces) => pieces,
+        };
+        let mut rendered = String::new();
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => rendered.push_str(text),
+                Piece::Key => rendered.push_str(key),
+                Piece::Value => rendered.push_str(value),
+                Piece::Score => {
+                    if let Some(score) = score {
+                        rendered.push_str(&score.to_string());
+                    }
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// Formats the fields as a line of `--porcelain` output. The fields are
+/// separated by tabs, and the backslashes, tabs and line breaks in them are
+/// escaped, so that every thing is on exactly one line.
+pub fn porcelain(fields: &[&str]) -> String {
+    let mut line = fields
+        .iter()
+        .map(|field| {
+            field
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+        })
+        .collect::<Vec<_>>()
+        .join("\t");
+    line.push('\n');
+    line
+}
+
+/// Puts the text on the system clipboard.
+pub fn copy_to_clipboard(text: &str) -> Result<(), arboard::Error> {
+    arboard::Clipboard::new()?.set_text(text)
+}
+
+/// Returns the text on the system clipboard.
+pub fn paste_from_clipboard() -> Result<String, arboard::Error> {
+    arboard::Clipboard::new()?.get_text()
+}
+
+/// Labels the result of a check as `ok` or `FAIL`, in green or red if
+/// `color` is set.
+pub fn check_label(passed: bool, color: bool) -> String {
+    match (passed, color) {
+        (true, true) => "\x1b[32mok\x1b[0m  ".to_string(),
+        (false, true) => "\x1b[31mFAIL\x1b[0m".to_string(),
+        (true, false) => "ok  ".to_string(),
+        (false, false) => "FAIL".to_string(),
+    }
+}
+
+/// Highlights the characters of the text at the given positions, using ANSI
+/// escape codes for bold red text.
+pub fn highlight(text: &str, indices: &[usize]) -> String {
+    surround(text, indices, "\x1b[1;31m", "\x1b[0m")
+}
+
+/// Puts the runs of characters of the text at the given positions in square
+/// brackets, to show them without colors.
+pub fn bracket(text: &str, indices: &[usize]) -> String {
+    surround(text, indices, "[", "]")
+}
+
+/// Puts `start` before and `end` after each run of characters of the text at
+/// the given positions.
+fn surround(text: &str, indices: &[usize], start: &str, end: &str) -> String {
+    let mut surrounded = String::new();
+    let mut in_run = false;
+    for (i, c) in text.chars().enumerate() {
+        let matched = indices.contains(&i);
+        if matched != in_run {
+            surrounded.push_str(if matched { start } else { end });
+            in_run = matched;
+        }
+        surrounded.push(c);
+    }
+    if in_run {
+        surrounded.push_str(end);
+    }
+    surrounded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("list files", &[0, 1, 5, 9]),
+            "\x1b[1;31mli\x1b[0mst \x1b[1;31mf\x1b[0mile\x1b[1;31ms\x1b[0m"
+        );
+        assert_eq!(highlight("list files", &[]), "list files");
+        assert_eq!(bracket("list files", &[0, 1, 5, 9]), "[li]st [f]ile[s]");
+    }
+
+    #[test]
+    fn test_porcelain() {
+        assert_eq!(porcelain(&["ls", "list files"]), "ls\tlist files\n");
+        assert_eq!(porcelain(&["a\tb", "c\\d\r\ne"]), "a\\tb\tc\\\\d\\r\\ne\n");
+    }
+
+    #[test]
+    fn test_format() {
+        let render = |format: &str| {
+            Format::parse(format).unwrap().render(
+                "ls",
+                "list files",
+                Some(42),
+                None,
+            )
+        };
+        assert_eq!(render("default"), "ls -> list files");
+        assert_eq!(render("keys"), "ls");
+        assert_eq!(render("values"), "list files");
+        assert_eq!(render("tsv"), "ls\tlist files");
+        assert_eq!(render("{value} ({key}): {score}"), "list files (ls): 42");
+        assert_eq!(render("{{{key}}}"), "{ls}");
+        assert_eq!(
+            Format::Default.render("ll", "list files", None, Some("ls")),
+            "ll -> list files (alias of ls)"
+        );
+
+        assert!(Format::parse("{name}").is_err());
+        assert!(Format::parse("{key").is_err());
+        assert!(Format::parse("key}").is_err());
+    }
+}
@@ -14,23 +14,359 @@
 
 use std::{
     env,
+    ffi::OsStr,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal},
     path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+use store::{StoreError, StoreErrorKind};
+
 /// Returns the path to the system config directory.
-pub fn get_config_dir() -> PathBuf {
-    match env::consts::OS.to_string().as_str() {
-        "windows" => Path::new(&env::var("APPDATA").unwrap()).to_path_buf(),
-        "macos" => Path::new(&env::var("HOME").unwrap())
+pub fn get_config_dir() -> Result<PathBuf, StoreError> {
+    // Returns the value of the given environment variable, if it is set and
+    // not empty.
+    let var = |name: &str| {
+        env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .ok_or(StoreError::App(StoreErrorKind::NoConfigDir))
+    };
+    Ok(match env::consts::OS.to_string().as_str() {
+        "windows" => Path::new(&var("APPDATA")?).to_path_buf(),
+        "macos" => Path::new(&var("HOME")?)
             .join("Library")
             .join("Application Support"),
-        "linux" => Path::new(&env::var("HOME").unwrap()).join(".config"),
-        _ => Path::new(&env::var("HOME").unwrap()).join(".config"),
+        _ => config_dir_from(
+            env::var_os("HOME").as_deref(),
+            env::var_os("XDG_CONFIG_HOME").as_deref(),
+        )
+        .ok_or(StoreError::App(StoreErrorKind::NoConfigDir))?,
+    })
+}
+
+/// Returns the config directory on systems other than Windows and macOS,
+/// given the values of the `HOME` and `XDG_CONFIG_HOME` environment
+/// variables.
+fn config_dir_from(
+    home: Option<&OsStr>,
+    xdg_config_home: Option<&OsStr>,
+) -> Option<PathBuf> {
+    // As per the XDG Base Directory specification, relative paths in
+    // XDG_CONFIG_HOME are invalid and should be ignored.
+    match xdg_config_home.map(Path::new) {
+        Some(dir) if dir.is_absolute() => Some(dir.to_path_buf()),
+        _ => home
+            .filter(|home| !home.is_empty())
+            .map(|home| Path::new(home).join(".config")),
+    }
+}
+
+/// The name of the profile used when none is given.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Returns the path to the directory that the store files are kept in.
+pub fn get_store_dir() -> Result<PathBuf, StoreError> {
+    Ok(get_config_dir()?.join("wwt"))
+}
+
+/// Returns the path to the store file of the given profile. The default
+/// profile is kept in `store.json`, where the store was kept before there
+/// were profiles.
+pub fn get_profile_path(profile: &str) -> Result<PathBuf, StoreError> {
+    let file_name = match profile {
+        DEFAULT_PROFILE => "store".to_string(),
+        profile => profile.to_string(),
+    };
+    Ok(get_store_dir()?.join(file_name + ".json"))
+}
+
+/// Returns the names of the profiles that have a store file, sorted.
+pub fn list_profiles() -> Result<Vec<String>, StoreError> {
+    let store_dir = get_store_dir()?;
+    if !store_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut profiles = Vec::new();
+    for entry in std::fs::read_dir(store_dir)? {
+        let path = entry?.path();
+        if path.extension() != Some("json".as_ref()) {
+            continue;
+        }
+        match path.file_stem().and_then(|s| s.to_str()) {
+            Some("store") => profiles.push(DEFAULT_PROFILE.to_string()),
+            // Profile names have no dots, so these are the undo snapshots
+            // and the like.
+            Some(profile) if profile.contains('.') => {}
+            Some(profile) => profiles.push(profile.to_string()),
+            None => {}
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
+/// Returns whether the output should be colored: only if it isn't turned off
+/// with `--no-color` (passed as `no_color`) or a non-empty `NO_COLOR`
+/// environment variable, and the standard output is a terminal.
+pub fn use_color(no_color: bool) -> bool {
+    use_color_from(
+        no_color,
+        env::var_os("NO_COLOR").as_deref(),
+        stdout_is_tty(),
+    )
+}
+
+/// Returns whether the output should be colored, given the flag, the value of
+/// the `NO_COLOR` environment variable and whether the standard output is a
+/// terminal, like [`use_color`].
+fn use_color_from(
+    no_color: bool,
+    no_color_env: Option<&OsStr>,
+    is_tty: bool,
+) -> bool {
+    !no_color && no_color_env.is_none_or(|value| value.is_empty()) && is_tty
+}
+
+/// Whether the standard output is a terminal, as opposed to a pipe or a file.
+/// Output meant for people, like colors, is only used on a terminal.
+pub fn stdout_is_tty() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// Whether the standard input is a terminal, so that there is someone to
+/// answer a prompt. Prompting otherwise would wait forever in a pipe or CI.
+pub fn stdin_is_tty() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Whether something was piped into the standard input, that is, it is a
+/// pipe, a socket or a non-empty file. Unlike `!stdin_is_tty()`, this is false
+/// for `/dev/null` and a closed standard input, as in scripts, cron and CI.
+#[cfg(unix)]
+pub fn stdin_is_piped() -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::io::AsFd;
+    let metadata = io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| File::from(fd).metadata());
+    match metadata {
+        Ok(metadata) => {
+            let file_type = metadata.file_type();
+            file_type.is_fifo()
+                || file_type.is_socket()
+                || (file_type.is_file() && metadata.len() > 0)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether something was piped into the standard input. Without a way to tell
+/// pipes from devices here, anything but a terminal counts.
+#[cfg(not(unix))]
+pub fn stdin_is_piped() -> bool {
+    !stdin_is_tty()
+}
+
+/// Creates the directory and its missing parents. On Unix, the created
+/// directories are only accessible by the current user, since the store can
+/// contain sensitive things like tokens.
+pub fn create_private_dir_all(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(path)
+    }
+    #[cfg(not(unix))]
+    {
+        fs::create_dir_all(path)
     }
 }
 
-/// Prints the given error message and exits the program.
-pub fn print_and_exit(msg: &str) -> ! {
-    eprintln!("{}", msg);
-    std::process::exit(1);
+/// Opens the file for writing with the given options, creating it if needed.
+/// On Unix, a created file is only readable and writable by the current
+/// user.
+pub fn open_private_file(
+    path: &Path,
+    options: &mut OpenOptions,
+) -> io::Result<File> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.write(true).create(true).open(path)
+}
+
+/// Returns the command to edit files with: the `VISUAL` environment variable,
+/// then `EDITOR`, and then `notepad` on Windows or `vi` elsewhere. The
+/// command can have arguments, separated by whitespace.
+pub fn editor() -> String {
+    editor_from(env::var("VISUAL").ok(), env::var("EDITOR").ok())
+}
+
+/// Returns the command to edit files with, given the values of the `VISUAL`
+/// and `EDITOR` environment variables, like [`editor`].
+fn editor_from(visual: Option<String>, editor: Option<String>) -> String {
+    visual
+        .into_iter()
+        .chain(editor)
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| {
+            if cfg!(windows) { "notepad" } else { "vi" }.to_string()
+        })
+}
+
+/// Returns a command that runs the given command line with the shell of the
+/// user: `$SHELL -c` (or `/bin/sh -c`) on Unix, and `%COMSPEC% /C` (or `cmd
+/// /C`) on Windows.
+pub fn shell_command(command: &str) -> Command {
+    let (variable, default, flag) = if cfg!(windows) {
+        ("COMSPEC", "cmd", "/C")
+    } else {
+        ("SHELL", "/bin/sh", "-c")
+    };
+    let shell = env::var(variable)
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| default.to_string());
+    let mut shell = Command::new(shell);
+    shell.arg(flag).arg(command);
+    shell
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Formats the given seconds since the Unix epoch as a UTC date and time,
+/// like `2022-01-05 13:45:00 UTC`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+    // Convert the days since the epoch to a civil date, based on
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Parses a date like `2022-01-05` into the seconds since the Unix epoch at
+/// its start, in UTC. Returns `None` if it is not a valid date.
+pub fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if year < 1970 || !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+    // The inverse of the conversion in `format_timestamp`, based on
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days as u64 * 86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_dir_from() {
+        let home = Some(OsStr::new("/home/user"));
+        let config_dir =
+            config_dir_from(home, Some(OsStr::new("/home/user/config")));
+        assert_eq!(config_dir.unwrap(), Path::new("/home/user/config"));
+
+        for xdg_config_home in [Some(""), Some("relative/config"), None] {
+            let config_dir =
+                config_dir_from(home, xdg_config_home.map(OsStr::new));
+            assert_eq!(config_dir.unwrap(), Path::new("/home/user/.config"));
+        }
+
+        assert_eq!(config_dir_from(Some(OsStr::new("")), None), None);
+        assert_eq!(config_dir_from(None, None), None);
+    }
+
+    #[test]
+    fn test_editor_from() {
+        let editor = |visual: Option<&str>, editor: Option<&str>| {
+            editor_from(visual.map(String::from), editor.map(String::from))
+        };
+        assert_eq!(editor(Some("code --wait"), Some("nano")), "code --wait");
+        assert_eq!(editor(Some(" "), Some("nano")), "nano");
+        assert_eq!(editor(None, Some("nano")), "nano");
+        assert_eq!(
+            editor(None, None),
+            if cfg!(windows) { "notepad" } else { "vi" }
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1641390300), "2022-01-05 13:45:00 UTC");
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-02-29"), Some(951782400));
+        assert_eq!(parse_date("2022-01-05"), Some(1641340800));
+        assert_eq!(parse_date("2022-02-29"), None);
+        assert_eq!(parse_date("2022-13-01"), None);
+        assert_eq!(parse_date("2022-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_use_color() {
+        // Turned off with the flag, whatever the output is.
+        assert!(!use_color(true));
+        assert!(!use_color_from(true, None, true));
+
+        assert!(use_color_from(false, None, true));
+        // Only a non-empty NO_COLOR turns the colors off.
+        assert!(use_color_from(false, Some(OsStr::new("")), true));
+        assert!(!use_color_from(false, Some(OsStr::new("1")), true));
+        // Never colored outside of a terminal.
+        assert!(!use_color_from(false, None, false));
+    }
 }
@@ -14,13 +14,18 @@
 
 extern crate assert_cmd;
 extern crate predicates;
+extern crate tempfile;
 
-use std::{collections::HashMap, fs, path::Path};
+use std::{collections::HashMap, fs, io::Write, path::Path};
 
 use assert_cmd::Command;
 use predicates::prelude::*;
 
-const TEST_STORE_PATH: &str = "./tests/store.json";
+// The store shared by the tests, kept in the temporary directory that Cargo
+// gives integration tests in the target directory, so that the store file,
+// its backups and its lock don't end up in the working tree.
+const TEST_STORE_PATH: &str =
+    concat!(env!("CARGO_TARGET_TMPDIR"), "/store.json");
 
 fn setup_cmd(truncate_file: bool) -> Command {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
@@ -40,19 +45,34 @@ fn setup_cmd(truncate_file: bool) -> Command {
 
 #[test]
 fn find_nonexistent_key() {
-    let mut cmd = setup_cmd(true);
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "bar", "A bar tool"]).assert();
+    assert.success().code(0);
 
-    let assert = cmd.args(&["find", "foo cli"]).assert();
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo cli"]).assert();
     assert
         .failure()
-        .code(1)
+        .code(2)
         .stderr(predicate::str::contains("No matches found."));
 }
 
+#[test]
+fn find_in_empty_store() {
+    let mut cmd = setup_cmd(true);
+
+    let assert = cmd.args(["find", "foo cli"]).assert();
+    assert
+        .failure()
+        .code(4)
+        .stderr(predicate::str::contains("Your store is empty."))
+        .stderr(predicate::str::contains("No matches found.").not());
+}
+
 #[test]
 fn delete_non_existent_command() {
     let mut cmd = setup_cmd(true);
-    let assert = cmd.args(&["forget", "foo"]).assert();
+    let assert = cmd.args(["forget", "foo"]).assert();
     assert
         .failure()
         .code(1)
@@ -63,7 +83,7 @@ fn delete_non_existent_command() {
 #[test]
 fn set_entry() {
     let mut cmd = setup_cmd(true);
-    let assert = cmd.args(&["set", "foo", "A foo cli"]).assert();
+    let assert = cmd.args(["set", "foo", "A foo cli"]).assert();
     assert.success().code(0);
 }
 
@@ -81,26 +101,27 @@ fn set_multiple_entries() {
         // File is not truncated here because the previous additions will be
         // removed if truncated.
         let mut cmd = setup_cmd(false);
-        let assert = cmd.args(&["set", name, description]).assert();
+        let assert = cmd.args(["set", name, description]).assert();
         assert.success().code(0);
     }
 
-    let store_contents = serde_json::from_str::<HashMap<String, String>>(
+    let store_contents = serde_json::from_str::<serde_json::Value>(
         &fs::read_to_string(TEST_STORE_PATH).unwrap(),
     )
     .unwrap();
 
-    assert_eq!(store_contents.iter().len(), 3);
+    assert_eq!(store_contents["version"], 2);
+    assert_eq!(store_contents["entries"].as_object().unwrap().len(), 3);
 }
 
 #[test]
 fn find_single_entry() {
     let mut set_cmd = setup_cmd(true);
-    let assert = set_cmd.args(&["set", "foo", "A foo cli"]).assert();
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
     assert.success().code(0);
 
     let mut find_cmd = setup_cmd(false);
-    let assert = find_cmd.args(&["find", "foo cli"]).assert();
+    let assert = find_cmd.args(["find", "foo cli"]).assert();
     assert
         .success()
         .stdout(predicate::str::contains("foo -> A foo cli"));
@@ -115,33 +136,61 @@ fn find_multiple_entries() {
     ];
     for (name, description) in entries.iter() {
         let mut cmd = setup_cmd(false);
-        let assert = cmd.args(&["set", name, description]).assert();
+        let assert = cmd.args(["set", name, description]).assert();
         assert.success().code(0);
     }
     let mut cmd = setup_cmd(false);
-    let assert = cmd.args(&["find", "Makes"]).assert();
+    let assert = cmd.args(["find", "Makes"]).assert();
     assert
         .success()
         .stdout(predicate::str::contains("make-me-a salad -> Makes salad"))
         .stdout(predicate::str::contains("make-me-a cookie -> Makes cookie"));
 }
 
+#[test]
+fn deterministic_order() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", store_dir.path().join("store.json"));
+        cmd.args(args).assert().success()
+    };
+    for key in ["key-c", "key-a", "key-d", "key-b"] {
+        cmd(&["set", key, "same value"]);
+    }
+    let expected =
+        "key-a -> same value\nkey-b -> same value\nkey-c -> same value\n\
+        key-d -> same value\n";
+    for _ in 0..3 {
+        cmd(&["list"]).stdout(expected);
+        cmd(&["find", "same value"]).stdout(expected);
+    }
+    let file =
+        std::fs::read_to_string(store_dir.path().join("store.json")).unwrap();
+    let positions = ["key-a", "key-b", "key-c", "key-d"]
+        .iter()
+        .map(|k| file.find(k).unwrap())
+        .collect::<Vec<_>>();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]));
+}
+
 #[test]
 fn delete_single_entry() {
     let mut set_cmd = setup_cmd(true);
-    let assert = set_cmd.args(&["set", "foo", "A foo cli"]).assert();
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
     assert.success().code(0);
 
     let mut delete_cmd = setup_cmd(false);
-    let assert = delete_cmd.args(&["delete", "foo"]).assert();
+    let assert = delete_cmd.args(["delete", "foo", "--yes"]).assert();
     assert.success().code(0);
 
+    // Nothing is left in the store after forgetting the only thing.
     let mut find_cmd = setup_cmd(false);
-    let assert = find_cmd.args(&["find", "foo cli"]).assert();
+    let assert = find_cmd.args(["find", "foo cli"]).assert();
     assert
         .failure()
-        .code(1)
-        .stderr(predicate::str::contains("No matches found."));
+        .code(4)
+        .stderr(predicate::str::contains("Your store is empty."));
 }
 
 #[test]
@@ -153,19 +202,19 @@ fn delete_multiple_entries() {
     ];
     for (name, description) in entries.iter() {
         let mut cmd = setup_cmd(false);
-        let assert = cmd.args(&["set", name, description]).assert();
+        let assert = cmd.args(["set", name, description]).assert();
         assert.success().code(0);
     }
 
     let mut cmd = setup_cmd(false);
-    let assert = cmd.args(&["delete", "make-me-a salad"]).assert();
+    let assert = cmd.args(["delete", "make-me-a salad", "-y"]).assert();
     assert.success().code(0);
 
     let mut find_cmd = setup_cmd(false);
-    let assert = find_cmd.args(&["find", "Makes salad"]).assert();
+    let assert = find_cmd.args(["find", "Makes salad"]).assert();
     assert
         .failure()
-        .code(1)
+        .code(2)
         .stderr(predicate::str::contains("No matches found."));
 }
 
@@ -179,12 +228,12 @@ fn find_all_entries() {
     ];
     for (name, description) in entries.iter() {
         let mut cmd = setup_cmd(false);
-        let assert = cmd.args(&["set", name, description]).assert();
+        let assert = cmd.args(["set", name, description]).assert();
         assert.success().code(0);
     }
 
     let mut cmd = setup_cmd(false);
-    let assert = cmd.args(&["find", ""]).assert();
+    let assert = cmd.args(["find", ""]).assert();
     assert
         .success()
         .stdout(predicate::str::contains("make-me-a salad -> Makes salad"))
@@ -193,3 +242,1978 @@ fn find_all_entries() {
             "cat FILE -> Reads FILE and displays contents",
         ));
 }
+
+#[test]
+fn list_entries_sorted() {
+    setup_cmd(true);
+    let entries = [
+        ("make-me-a salad", "Makes salad"),
+        ("cat FILE", "Reads FILE and displays contents"),
+        ("make-me-a cookie", "Makes cookie"),
+    ];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout(
+        "cat FILE -> Reads FILE and displays contents\n\
+        make-me-a cookie -> Makes cookie\n\
+        make-me-a salad -> Makes salad\n",
+    );
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--sort", "description"]).assert();
+    assert.success().stdout(
+        "make-me-a cookie -> Makes cookie\n\
+        make-me-a salad -> Makes salad\n\
+        cat FILE -> Reads FILE and displays contents\n",
+    );
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--sort", "length", "--reverse"]).assert();
+    assert.success().stdout(
+        "cat FILE -> Reads FILE and displays contents\n\
+        make-me-a cookie -> Makes cookie\n\
+        make-me-a salad -> Makes salad\n",
+    );
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "cookie", "--first"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--sort", "recent"]).assert();
+    assert.success().stdout(predicate::str::starts_with(
+        "make-me-a cookie -> Makes cookie\n",
+    ));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--sort", "value"]).assert();
+    assert.success().stdout(predicate::str::starts_with(
+        "make-me-a cookie -> Makes cookie\n",
+    ));
+}
+
+#[test]
+fn list_empty_store() {
+    let mut cmd = setup_cmd(true);
+    let assert = cmd.args(["list"]).assert();
+    assert
+        .success()
+        .code(0)
+        .stdout("")
+        .stderr(predicate::str::contains("store is empty"));
+}
+
+#[test]
+fn find_with_limit() {
+    setup_cmd(true);
+    let entries = [
+        ("make-me-a salad", "Makes salad"),
+        ("make-me-a cookie", "Makes cookie"),
+        ("make-me-a sandwich", "Makes sandwich"),
+    ];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "Makes", "--limit", "2"]).assert();
+    let output = assert.success().get_output().stdout.clone();
+    assert_eq!(String::from_utf8(output).unwrap().lines().count(), 2);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "Makes", "-n", "0"]).assert();
+    assert
+        .failure()
+        .stderr(predicate::str::contains("must be greater than 0"));
+
+    // The limit can be set with the environment instead.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .env("WWT_FIND_LIMIT", "1")
+        .args(["find", "Makes"])
+        .assert();
+    let output = assert.success().get_output().stdout.clone();
+    assert_eq!(String::from_utf8(output).unwrap().lines().count(), 1);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .env("WWT_FIND_LIMIT", "1")
+        .args(["find", "Makes", "--limit", "2"])
+        .assert();
+    let output = assert.success().get_output().stdout.clone();
+    assert_eq!(String::from_utf8(output).unwrap().lines().count(), 2);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .env("WWT_FIND_LIMIT", "ten")
+        .args(["find", "Makes"])
+        .assert();
+    assert
+        .failure()
+        .stderr(predicate::str::contains("invalid digit"));
+}
+
+#[test]
+fn describe_entry() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["describe", "FOO"]).assert();
+    assert.success().stdout("A foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["describe", "bar"]).assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Key not found: bar"));
+}
+
+#[test]
+fn find_entries_with_tag() {
+    setup_cmd(true);
+    let entries = [
+        ("ls", "List files", "shell"),
+        ("Ctrl+O", "List files to open", "editor"),
+    ];
+    for (name, description, tag) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert =
+            cmd.args(["set", name, description, "--tag", tag]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "List files", "--tag", "shell"]).assert();
+    assert.success().stdout("ls -> List files\n");
+}
+
+#[test]
+fn find_verbose() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut find_cmd = setup_cmd(false);
+    let assert = find_cmd.args(["find", "foo cli", "--verbose"]).assert();
+    assert
+        .success()
+        .stdout(predicate::str::contains("foo -> A foo cli\n    created: "))
+        .stdout(predicate::str::contains("last found: unknown"));
+
+    // The previous search is recorded as the last time the entry was found.
+    let mut list_cmd = setup_cmd(false);
+    let assert = list_cmd.args(["list", "--verbose"]).assert();
+    assert.success().stdout(
+        predicate::str::contains("last found: ")
+            .and(predicate::str::contains("last found: unknown").not())
+            .and(predicate::str::contains("times found: 1")),
+    );
+}
+
+#[test]
+fn count_entries() {
+    setup_cmd(true);
+    let entries = [
+        ("foo", "A foo cli"),
+        ("bar", "A bar cli"),
+        ("baz", "A baz cli"),
+    ];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["count"]).assert();
+    assert.success().stdout("3\n");
+}
+
+#[test]
+fn export_entries() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo, bar cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["export", "csv"]).assert();
+    assert
+        .success()
+        .stdout("key,description\r\nfoo,\"A foo, bar cli\"\r\n");
+
+    let output = tempfile::NamedTempFile::new().unwrap();
+    let mut cmd = setup_cmd(false);
+    let output_path = output.path().to_str().unwrap();
+    let assert = cmd.args(["export", "json", "-o", output_path]).assert();
+    assert.success().stdout("");
+    let exported = serde_json::from_str::<HashMap<String, String>>(
+        &fs::read_to_string(output.path()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(exported.get("foo").unwrap(), "A foo, bar cli");
+}
+
+#[test]
+fn import_entries() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut input = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+    write!(input, "key,description\nfoo,New foo\nbar,\"A bar, cli\"\n")
+        .unwrap();
+    let input_path = input.path().to_str().unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["import", input_path]).assert();
+    assert.success().stdout("").stderr("1 added, 1 skipped.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["import", input_path, "--overwrite"]).assert();
+    assert.success().stdout("").stderr("2 updated.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert
+        .success()
+        .stdout("bar -> A bar, cli\nfoo -> New foo\n");
+}
+
+#[test]
+fn import_ignoring_case() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "git", "version control"]).assert();
+    assert.success().code(0);
+
+    let mut input = tempfile::NamedTempFile::new().unwrap();
+    write!(input, "{{\"Git\": \"track changes\", \"ls\": \"list\"}}").unwrap();
+    let input_path = input.path().to_str().unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["import", input_path, "--ignore-case"]).assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr("1 added, 1 skipped, 1 matched in another case.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["import", input_path, "--ignore-case", "--overwrite"])
+        .assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr("2 updated, 1 matched in another case.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert
+        .success()
+        .stdout("git -> track changes\nls -> list\n");
+}
+
+#[test]
+fn import_malformed_json() {
+    setup_cmd(true);
+    let mut input = tempfile::NamedTempFile::new().unwrap();
+    write!(input, "{{\"foo\": ").unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["import", input.path().to_str().unwrap()])
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("JSON error"));
+}
+
+#[test]
+fn missing_config_dir() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .env_remove("WWT_STORE_PATH")
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("HOME")
+        .env_remove("APPDATA")
+        .args(["list"])
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("set WWT_STORE_PATH"));
+
+    // The config directory is not needed if the store path is given.
+    let mut cmd = setup_cmd(true);
+    let assert = cmd
+        .env_remove("HOME")
+        .env_remove("APPDATA")
+        .args(["list"])
+        .assert();
+    assert.success();
+}
+
+#[test]
+fn concurrent_set_entries() {
+    setup_cmd(true);
+    let bin = assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME"));
+    let children = (0..8)
+        .map(|i| {
+            std::process::Command::new(&bin)
+                .env("WWT_STORE_PATH", TEST_STORE_PATH)
+                .args(["set", &format!("foo{}", i), "A foo cli"])
+                .spawn()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["count"]).assert();
+    assert.success().stdout("8\n");
+}
+
+#[test]
+fn generate_completions() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd.args(["completions", "bash"]).assert();
+    assert
+        .success()
+        .stdout(predicate::str::contains("_what-was-that()"));
+}
+
+#[test]
+fn find_json() {
+    setup_cmd(true);
+    let entries = [("foo", "A foo -> cli"), ("bar", "A bar cli")];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo", "--json"]).assert();
+    let output = assert.success().get_output().stdout.clone();
+    let matches = serde_json::from_slice::<serde_json::Value>(&output).unwrap();
+    assert_eq!(matches[0]["key"], "foo");
+    assert_eq!(matches[0]["description"], "A foo -> cli");
+    assert!(matches[0]["score"].is_i64());
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--json"]).assert();
+    let output = assert.success().get_output().stdout.clone();
+    let entries = serde_json::from_slice::<serde_json::Value>(&output).unwrap();
+    assert_eq!(entries.as_array().unwrap().len(), 2);
+    assert!(entries[0].get("score").is_none());
+}
+
+#[test]
+fn forget_without_confirmation() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["forget", "foo"]).assert();
+    assert
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("foo -> A foo cli"))
+        .stderr(predicate::str::contains("--yes"));
+
+    let mut find_cmd = setup_cmd(false);
+    let assert = find_cmd.args(["find", "foo cli"]).assert();
+    assert.success();
+}
+
+#[test]
+fn find_by_key() {
+    setup_cmd(true);
+    let entries = [("ls", "Print directory contents"), ("cat", "Show a file")];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "ls"]).assert();
+    assert.success().stdout("ls -> Print directory contents\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "ls", "--values-only"]).assert();
+    assert.failure().code(2);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "ls", "--keys-only", "--both"]).assert();
+    assert.failure().code(2);
+}
+
+#[test]
+fn sqlite_backend() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.db");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["set", "foo", "A foo cli"]).success();
+    cmd(&["set", "bar", "A bar cli"]).success();
+    cmd(&["find", "foo cli"])
+        .success()
+        .stdout(predicate::str::contains("foo -> A foo cli"));
+    cmd(&["forget", "foo", "--yes"]).success();
+    cmd(&["list"]).success().stdout("bar -> A bar cli\n");
+
+    // The store is not JSON, so it can't be read as a JSON store.
+    cmd(&["list", "--backend", "json"]).failure().code(1);
+}
+
+#[test]
+fn repair_corrupt_store() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    fs::write(&store_path, r#"{"foo": "A foo cli",}"#).unwrap();
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["list"])
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(store_path.to_str().unwrap()))
+        .stderr(predicate::str::contains("--repair"));
+
+    cmd(&["list", "--repair"])
+        .success()
+        .stderr(predicate::str::contains("store.json.bak"));
+    let backup_path = store_dir.path().join("store.json.bak");
+    assert_eq!(
+        fs::read_to_string(backup_path).unwrap(),
+        r#"{"foo": "A foo cli",}"#
+    );
+    cmd(&["list"]).success().stdout("");
+}
+
+#[test]
+fn clear_entries() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["set", "foo", "A foo cli"]).success();
+    cmd(&["set", "bar", "A bar cli"]).success();
+
+    cmd(&["clear"])
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("--yes"));
+    cmd(&["count"]).success().stdout("2\n");
+
+    cmd(&["clear", "--yes"])
+        .success()
+        .stdout("")
+        .stderr("2 forgotten.\n");
+    cmd(&["count"]).success().stdout("0\n");
+}
+
+#[test]
+fn find_with_min_score() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "fc", "--min-score", "10"]).assert();
+    assert.success().stdout("foo -> A foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "fc", "--min-score", "1000"]).assert();
+    assert
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("No matches found."));
+}
+
+#[test]
+fn find_count() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd.args(["set", "bar", "A bar cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "cli", "--count"]).assert();
+    assert.success().stdout("2\n");
+
+    // No matches isn't a failure when counting.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "qux", "--count"]).assert();
+    assert.success().code(0).stdout("0\n");
+}
+
+#[test]
+fn case_insensitive_keys() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let store_path = store_path.to_str().unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["-s", store_path, "--case-insensitive-keys", "set", "Git"])
+        .arg("version control")
+        .assert();
+    assert.success();
+
+    // The setting is kept in the store, so the flag isn't needed anymore.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args([
+            "-s",
+            store_path,
+            "set",
+            "git",
+            "distributed version control",
+        ])
+        .assert();
+    assert.success();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["-s", store_path, "list"]).assert();
+    assert
+        .success()
+        .stdout("Git -> distributed version control\n");
+
+    // The setting can be turned off again.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["-s", store_path, "--case-sensitive-keys", "set", "git"])
+        .arg("lowercase git")
+        .assert();
+    assert.success();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["-s", store_path, "list"]).assert();
+    assert
+        .success()
+        .stdout("Git -> distributed version control\ngit -> lowercase git\n");
+}
+
+#[test]
+fn list_since_until() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--since", "1d"]).assert();
+    assert.success().stdout("foo -> A foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--until", "2000-01-01"]).assert();
+    assert.success().stdout("");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--since", "last week"]).assert();
+    assert
+        .failure()
+        .stderr(predicate::str::contains("expected a date like 2022-01-05"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn clipboard_without_display() {
+    // Without a display there is no clipboard to export to or import from.
+    let args: [&[&str]; 2] = [
+        &["export", "json", "--clipboard"],
+        &["import", "--clipboard"],
+    ];
+    for args in args {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY")
+            .args(args)
+            .assert();
+        assert
+            .failure()
+            .code(1)
+            .stderr(predicate::str::contains("The clipboard is not available"));
+    }
+}
+
+#[test]
+fn random() {
+    let mut cmd = setup_cmd(true);
+    let assert = cmd.args(["random"]).assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr(predicate::str::contains("Your store is empty."));
+
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["random", "--count", "5"]).assert();
+    assert.success().stdout("foo -> A foo cli\n");
+}
+
+#[test]
+fn pin_and_unpin() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "list", "list files"]).assert();
+    assert.success().code(0);
+
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd.args(["set", "ls", "list"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["pin", "ls"]).assert();
+    assert.success();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "list", "--first"]).assert();
+    assert.success().stdout("ls\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--verbose"]).assert();
+    assert
+        .success()
+        .stdout(predicate::str::contains("ls -> list *\n"))
+        .stdout(predicate::str::contains("list -> list files\n"));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["unpin", "ls"]).assert();
+    assert.success();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "list", "--first"]).assert();
+    assert.success().stdout("list\n");
+}
+
+#[test]
+fn command_aliases() {
+    for (command, alias) in
+        [("remember", "set"), ("find", "get"), ("forget", "delete")]
+    {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.arg("--help").assert();
+        assert
+            .success()
+            .stdout(predicate::str::contains(format!("[aliases: {}]", alias)));
+
+        // Both names of each command do the same.
+        for name in [command, alias] {
+            let mut set_cmd = setup_cmd(true);
+            let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+            assert.success().code(0);
+
+            let mut cmd = setup_cmd(false);
+            let assert = match command {
+                "remember" => cmd.args([name, "bar", "A bar cli"]).assert(),
+                "find" => cmd.args([name, "foo cli"]).assert(),
+                _ => cmd.args([name, "foo", "--yes"]).assert(),
+            };
+            assert.success().code(0);
+
+            let mut cmd = setup_cmd(false);
+            let expected = match command {
+                "remember" => "bar -> A bar cli\nfoo -> A foo cli\n",
+                "find" => "foo -> A foo cli\n",
+                _ => "",
+            };
+            cmd.args(["list"]).assert().success().stdout(expected);
+        }
+    }
+}
+
+#[test]
+fn find_inverted() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd
+        .args(["set", "git log", "show the history"])
+        .assert();
+    assert.success().code(0);
+
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd.args(["set", "ls", "list files"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "git", "--invert"]).assert();
+    assert.success().stdout("ls -> list files\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "", "--invert"]).assert();
+    assert.failure().code(2);
+}
+
+#[test]
+fn description_length_limit() {
+    let mut cmd = setup_cmd(true);
+    let assert = cmd
+        .args(["--max-length", "5", "set", "foo", "12345"])
+        .assert();
+    assert.success();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["--max-length", "5", "set", "bar", "123456"])
+        .assert();
+    assert.failure().code(1).stderr(predicate::str::contains(
+        "The description is 6 characters long, longer than the limit of 5",
+    ));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["--max-length", "5", "set", "bar", "123456", "--force"])
+        .assert();
+    assert.success();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("bar -> 123456\nfoo -> 12345\n");
+}
+
+#[test]
+fn remember_from_file() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", store_dir.path().join("store.json"));
+        cmd.args(args).assert()
+    };
+    let script_path = store_dir.path().join("backup.sh");
+    std::fs::write(&script_path, "rsync -a ~ /mnt/backup\nsync\n").unwrap();
+    let script_path = script_path.to_str().unwrap();
+
+    cmd(&["remember", "backup", "--from-file", script_path]).success();
+    cmd(&["list"])
+        .success()
+        .stdout("backup -> rsync -a ~ /mnt/backup\n          sync\n");
+    cmd(&["find", "backup", "--format", "values"])
+        .success()
+        .stdout("rsync -a ~ /mnt/backup\nsync\n");
+    cmd(&["find", "backup", "--json"]).success().stdout(
+        predicate::str::contains(r#""rsync -a ~ /mnt/backup\nsync""#),
+    );
+
+    cmd(&[
+        "--max-length",
+        "10",
+        "remember",
+        "long",
+        "--from-file",
+        script_path,
+    ])
+    .failure()
+    .stderr(predicate::str::contains("longer than the limit of 10"));
+    cmd(&["remember", "ls", "list", "--from-file", script_path]).failure();
+    cmd(&["remember", "missing", "--from-file", "missing.sh"])
+        .failure()
+        .stderr(predicate::str::contains("File not found"));
+}
+
+#[test]
+fn export_and_remove() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let archive_path = archive_dir.path().join("archive.json");
+    let archive_path = archive_path.to_str().unwrap();
+
+    for (name, description) in [
+        ("work/ssh", "log in"),
+        ("work/vpn", "connect"),
+        ("ls", "list"),
+    ] {
+        let mut cmd = setup_cmd(name == "work/ssh");
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["export", "json", "--pattern", "work/*", "--remove"])
+        .args(["-o", archive_path])
+        .assert();
+    assert
+        .success()
+        .stdout(format!("Moved 2 things to {}.\n", archive_path));
+
+    let archived = serde_json::from_str::<HashMap<String, String>>(
+        &fs::read_to_string(archive_path).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(archived.len(), 2);
+    assert_eq!(archived["work/vpn"], "connect");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("ls -> list\n");
+
+    // Nothing is exported if one of the things is missing.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["export", "csv", "--keys", "ls,cat"]).assert();
+    assert.failure().code(1).stdout("");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["export", "csv", "--keys", "ls"]).assert();
+    assert.success().stdout("key,description\r\nls,list\r\n");
+}
+
+#[test]
+fn store_format_flag() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store");
+    let store_path = store_path.to_str().unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["-s", store_path, "--store-format", "yaml"])
+        .args(["set", "foo", "A foo cli"])
+        .assert();
+    assert.success();
+
+    let content = fs::read_to_string(store_path).unwrap();
+    assert!(content.starts_with("version: 2\n"), "{}", content);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["-s", store_path, "--store-format", "yaml", "list"])
+        .assert();
+    assert.success().stdout("foo -> A foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["-s", store_path, "--store-format", "csv", "list"])
+        .assert();
+    assert.failure().code(2);
+}
+
+#[test]
+fn remember_json() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "ls", "list"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args([
+            "remember",
+            "--json",
+            r#"{"ls": "list files", "cat": "print"}"#,
+        ])
+        .assert();
+    assert.success().stdout("").stderr("1 added, 1 updated.\n");
+
+    // Nothing is remembered if any of the things can't be.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args([
+            "remember",
+            "--json",
+            r#"{"tree": "show the tree", "": "x"}"#,
+        ])
+        .assert();
+    assert.failure().code(1);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("cat -> print\nls -> list files\n");
+}
+
+#[test]
+fn store_path_flag() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let store_path = store_path.to_str().unwrap();
+
+    let mut cmd = setup_cmd(true);
+    let assert = cmd
+        .args(["--store-path", store_path, "set", "foo", "A foo cli"])
+        .assert();
+    assert.success();
+
+    // The flag takes precedence over the environment variable.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "-s", store_path]).assert();
+    assert.success().stdout("foo -> A foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("");
+}
+
+#[test]
+fn profiles() {
+    let config_dir = tempfile::tempdir().unwrap();
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env_remove("WWT_STORE_PATH")
+            .env("XDG_CONFIG_HOME", config_dir.path())
+            .env("HOME", config_dir.path())
+            .env("APPDATA", config_dir.path())
+            .args(args)
+            .assert()
+    };
+
+    cmd(&["profiles", "list"]).success().stdout("");
+    cmd(&["set", "foo", "A foo cli"]).success();
+    cmd(&["--profile", "work", "set", "bar", "A bar cli"]).success();
+
+    cmd(&["list"]).success().stdout("foo -> A foo cli\n");
+    cmd(&["list", "-p", "work"])
+        .success()
+        .stdout("bar -> A bar cli\n");
+    cmd(&["profiles", "list"])
+        .success()
+        .stdout("default\nwork\n");
+
+    cmd(&["list", "--profile", "../work"]).failure().code(2);
+    cmd(&["list", "--profile", "store"])
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("\"store\" is reserved"));
+}
+
+#[test]
+fn dedupe_entries() {
+    setup_cmd(true);
+    let entries = [
+        ("ls", "list files"),
+        ("dir", "list files"),
+        ("cat", "Show a file"),
+    ];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["dedupe"]).assert();
+    assert.success().stdout("list files: dir, ls\n");
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["count"]).assert();
+    assert.success().stdout("3\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["dedupe", "--merge"]).assert();
+    assert
+        .success()
+        .stdout("list files: dir, ls\n")
+        .stderr("Forgetting ls (same as dir)\n1 forgotten.\n");
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert
+        .success()
+        .stdout("cat -> Show a file\ndir -> list files\n");
+}
+
+#[test]
+fn find_with_regex() {
+    setup_cmd(true);
+    let entries = [
+        ("git log", "Show the commit history"),
+        ("tig", "A git interface"),
+    ];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "--regex", "^git"]).assert();
+    assert
+        .success()
+        .stdout("git log -> Show the commit history\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "--regex", "(git"]).assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Invalid regex"));
+}
+
+#[test]
+fn find_with_substring() {
+    setup_cmd(true);
+    let entries = [("ls", "List files"), ("find", "Search files by name")];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "--substring", "list f"]).assert();
+    assert.success().stdout("ls -> List files\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "--substring", "lf"]).assert();
+    assert.failure().code(2);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "--substring", "--regex", "ls"]).assert();
+    assert
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn show_stats() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd
+        .args(["set", "foo", "A foo cli", "--tag", "cli"])
+        .assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["stats"]).assert();
+    assert
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Things: 1\n\
+            Average description length: 9.0\n\
+            Longest description: foo (9 characters)\n\
+            Oldest thing: foo (",
+        ))
+        .stdout(predicate::str::ends_with("Tags:\n    cli: 1\n"));
+}
+
+#[test]
+fn set_entry_from_stdin() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd
+        .args(["set", "foo", "-"])
+        .write_stdin("A foo cli\nwith more\n")
+        .assert();
+    assert.success().code(0);
+
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd
+        .args(["set", "bar"])
+        .write_stdin("A bar cli")
+        .assert();
+    assert.success().code(0);
+
+    // The argument wins over the standard input.
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd
+        .args(["set", "baz", "A baz cli"])
+        .write_stdin("Ignored")
+        .assert();
+    assert
+        .success()
+        .stderr(predicate::str::contains("ignoring the standard input"));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout(
+        "bar -> A bar cli\nbaz -> A baz cli\nfoo -> A foo cli\n       \
+        with more\n",
+    );
+}
+
+#[test]
+fn set_entry_with_null_stdin() {
+    // Scripts, cron and CI often run with /dev/null as the standard input,
+    // which is not piped input to warn about. assert_cmd always pipes the
+    // standard input, so run the binary through the standard library.
+    use assert_cmd::prelude::*;
+    setup_cmd(true);
+    let mut set_cmd =
+        std::process::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = set_cmd
+        .env("WWT_STORE_PATH", TEST_STORE_PATH)
+        .args(["set", "foo", "A foo cli"])
+        .stdin(std::process::Stdio::null())
+        .assert();
+    assert.success().stderr("");
+}
+
+#[test]
+fn alias_entry() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["alias", "bar", "foo"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["edit", "foo", "A foo or bar cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo or bar cli"]).assert();
+    assert.success().stdout(
+        "bar -> A foo or bar cli (alias of foo)\nfoo -> A foo or bar cli\n",
+    );
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["alias", "baz", "missing"]).assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Key not found: missing"));
+}
+
+#[test]
+fn restore_backup() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["set", "foo", "A foo cli"]).success();
+    cmd(&["forget", "foo", "--yes"]).success();
+    cmd(&["list"]).success().stdout("");
+
+    cmd(&["restore"])
+        .success()
+        .stderr(predicate::str::contains("Restored backup number 1."));
+    cmd(&["list"]).success().stdout("foo -> A foo cli\n");
+
+    cmd(&["restore", "2"])
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("No backup number 2"));
+}
+
+#[test]
+fn find_first() {
+    setup_cmd(true);
+    let entries = [
+        ("ls -l", "list files"),
+        ("ls", "list files"),
+        ("tree", "show the directory tree"),
+    ];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "list files", "--first"]).assert();
+    assert.success().stdout("ls\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo cli", "--one"]).assert();
+    assert.failure().code(2).stdout("");
+}
+
+#[test]
+fn overwrite_entry() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert
+        .success()
+        .stderr(predicate::str::contains("Updated").not());
+
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd.args(["set", "foo", "A new foo cli"]).assert();
+    assert
+        .success()
+        .stderr(predicate::str::contains("Updated 'foo' (was: A foo cli)\n"));
+
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd
+        .args(["set", "foo", "Another foo cli", "--no-clobber"])
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Key already exists: foo"));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["describe", "foo"]).assert();
+    assert.success().stdout("A new foo cli\n");
+}
+
+#[test]
+fn find_and_copy_without_clipboard() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    // There is no clipboard without a display, but the matches are still
+    // printed.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .args(["find", "foo cli", "--copy"])
+        .assert();
+    assert
+        .success()
+        .stdout("foo -> A foo cli\n")
+        .stderr(predicate::str::contains("clipboard"));
+}
+
+#[test]
+fn list_namespace() {
+    setup_cmd(true);
+    let entries = [
+        ("git/log", "Show the commit history"),
+        ("docker/prune", "Remove unused data"),
+        ("gitk", "Browse the repository"),
+    ];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--namespace", "git"]).assert();
+    assert
+        .success()
+        .stdout("git/log -> Show the commit history\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["namespaces"]).assert();
+    assert.success().stdout("docker\ngit\n");
+}
+
+#[test]
+fn dry_run() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["clear", "--dry-run"]).assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr("[dry-run] 1 forgotten.\n[dry-run] No changes were saved.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["set", "bar", "A bar cli", "--dry-run"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("foo -> A foo cli\n");
+}
+
+#[test]
+fn no_save() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+    let content = fs::read_to_string(TEST_STORE_PATH).unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["clear", "--yes", "--no-save"]).assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr("1 forgotten.\n[no-save] Nothing was saved.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["set", "bar", "A bar cli", "--no-save"]).assert();
+    assert.success().code(0);
+
+    assert_eq!(fs::read_to_string(TEST_STORE_PATH).unwrap(), content);
+}
+
+#[test]
+fn undo_change() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["forget", "foo", "--yes"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["undo"]).assert();
+    assert.success().stderr("Undid the last change.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("foo -> A foo cli\n");
+
+    // The snapshot is used up.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["undo"]).assert();
+    assert
+        .failure()
+        .stderr(predicate::str::contains("nothing to undo"));
+}
+
+#[test]
+fn find_to_output_file() {
+    let output_dir = tempfile::tempdir().unwrap();
+    let output = output_dir.path().join("docs").join("found.json");
+    let output = output.to_str().unwrap();
+
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo", "--json", "-o", output]).assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr(predicate::str::contains("Wrote 1 things to"));
+    let found = fs::read_to_string(output).unwrap();
+    assert!(found.contains(r#""key": "foo""#));
+
+    // Whatever was in the file is replaced.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--output", output]).assert();
+    assert.success().stdout("");
+    assert_eq!(fs::read_to_string(output).unwrap(), "foo -> A foo cli\n");
+}
+
+#[test]
+fn set_blank_key() {
+    let mut cmd = setup_cmd(true);
+    let assert = cmd.args(["set", " ", "A blank cli"]).assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Invalid name"));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("");
+}
+
+#[test]
+fn touch_entry() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["touch", "foo"]).assert();
+    assert.success().stdout("");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--verbose"]).assert();
+    assert.success().stdout(
+        predicate::str::contains("last found: unknown")
+            .not()
+            .and(predicate::str::contains("times found: 0")),
+    );
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["touch", "bar"]).assert();
+    assert.failure().code(1);
+}
+
+#[test]
+fn prune_entries() {
+    setup_cmd(true);
+    std::fs::write(
+        TEST_STORE_PATH,
+        r#"{
+            "old": {"description": "Found long ago", "last_accessed": 1000},
+            "unknown": "Never found"
+        }"#,
+    )
+    .unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["prune", "--older-than", "90d", "--dry-run"])
+        .assert();
+    assert.success().stdout("").stderr(
+        "[dry-run] Would forget old\n[dry-run] 1 forgotten.\n\
+            [dry-run] No changes were saved.\n",
+    );
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["prune", "--older-than", "6m"]).assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr("Forgetting old\n1 forgotten.\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("unknown -> Never found\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["prune", "--older-than", "90"]).assert();
+    assert.failure().code(2);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["prune", "--older-than", "99999999999999999d"])
+        .assert();
+    assert
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("duration is too large"));
+}
+
+#[test]
+fn quiet_and_verbose() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli", "-v"]).assert();
+    assert
+        .success()
+        .stderr(predicate::str::contains("Remembered 'foo'."));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["--quiet", "set", "foo", "A new foo cli"])
+        .assert();
+    assert.success().stderr("");
+
+    // The results are still printed.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo", "-q"]).assert();
+    assert.success().stdout("foo -> A new foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "bar", "-q"]).assert();
+    assert.failure().code(2).stderr("");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "-q", "-v"]).assert();
+    assert.failure().code(2);
+}
+
+#[test]
+fn set_entry_with_note() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd
+        .args(["set", "foo", "A foo cli", "--note", "See foo.example"])
+        .assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo cli", "--verbose"]).assert();
+    assert.success().stdout(predicate::str::starts_with(
+        "foo -> A foo cli\n    note: See foo.example\n",
+    ));
+
+    // The note is not matched.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "example", "--values-only"]).assert();
+    assert.failure().code(2);
+}
+
+#[test]
+fn find_fields() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd
+        .args(["set", "foo", "A foo cli", "--note", "See foo.example"])
+        .assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "example", "--fields", "note"]).assert();
+    assert.success().stdout("foo -> A foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["find", "example", "--fields", "key,description"])
+        .assert();
+    assert.failure().code(2);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo", "--fields", "name"]).assert();
+    assert
+        .failure()
+        .stderr(predicate::str::contains("expected key, description"));
+}
+
+#[test]
+fn find_case_sensitive() {
+    setup_cmd(true);
+    let entries = [("get", "Fetch it"), ("GET", "Fetch it loudly")];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["find", "GET", "--keys-only", "--case-sensitive"])
+        .assert();
+    assert.success().stdout("GET -> Fetch it loudly\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["find", "get", "--keys-only", "--smart-case"])
+        .assert();
+    assert.success().stdout(predicate::str::contains("GET"));
+}
+
+#[test]
+fn batch_operations() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "cat", "Print files"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .arg("batch")
+        .write_stdin("set \"ls -l\" List files\nforget cat\nforget dog\nsit\n")
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stdout("line 1: remembered 'ls -l'\nline 2: forgot 'cat'\n")
+        .stderr(
+            predicate::str::contains("line 3: ")
+                .and(predicate::str::contains("Invalid operation")),
+        );
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("ls -l -> List files\n");
+}
+
+#[test]
+fn find_with_format() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["find", "foo", "--format", "{value} ({key})"])
+        .assert();
+    assert.success().stdout("A foo cli (foo)\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list", "--format", "tsv"]).assert();
+    assert.success().stdout("foo\tA foo cli\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo", "--json"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo", "--format", "{name}"]).assert();
+    assert
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("unknown placeholder {name}"));
+}
+
+#[test]
+fn store_path_is_directory() {
+    let store_dir = tempfile::tempdir().unwrap();
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .env("WWT_STORE_PATH", store_dir.path())
+        .args(["list"])
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("is a directory, expected a file"));
+}
+
+#[test]
+fn find_with_contains() {
+    setup_cmd(true);
+    let entries = [("ls", "List files"), ("exa", "List files, with colors")];
+    for (name, description) in entries.iter() {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, description]).assert();
+        assert.success().code(0);
+    }
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["find", "list files", "--contains", "Colors"])
+        .assert();
+    assert.success().stdout("exa -> List files, with colors\n");
+}
+
+#[test]
+fn find_with_debug() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "foo", "A foo cli"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "foo", "--debug"]).assert();
+    assert
+        .success()
+        .stdout("foo -> A foo cli\n")
+        .stderr(predicate::str::contains("[debug] foo: score="));
+}
+
+#[test]
+fn find_with_explain() {
+    let mut set_cmd = setup_cmd(true);
+    let assert = set_cmd.args(["set", "ls", "list files"]).assert();
+    assert.success().code(0);
+    let mut set_cmd = setup_cmd(false);
+    let assert = set_cmd.args(["set", "cat", "print files"]).assert();
+    assert.success().code(0);
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "lsf", "--explain"]).assert();
+    assert
+        .success()
+        .stdout("ls -> list files\n")
+        .stderr(predicate::str::contains("[explain] 1. ls: score="))
+        .stderr(predicate::str::contains(
+            "field=Description pinned=false uses=0\n\
+            [explain]    [l]i[s]t [f]iles\n",
+        ));
+
+    // Without the flag, nothing is explained.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["find", "lsf"]).assert();
+    assert
+        .success()
+        .stderr(predicate::str::contains("[explain]").not());
+}
+
+#[test]
+fn forget_by_pattern() {
+    setup_cmd(true);
+    for name in ["docker ps", "docker rm", "git log"] {
+        let mut cmd = setup_cmd(false);
+        let assert = cmd.args(["set", name, "A command"]).assert();
+        assert.success().code(0);
+    }
+
+    // The things are listed, but not forgotten without a confirmation.
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["forget", "--pattern", "docker *"]).assert();
+    assert.failure().code(3).stderr(predicate::str::contains(
+        "docker ps -> A command\ndocker rm -> A command\n",
+    ));
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd
+        .args(["forget", "--pattern", "^docker", "--regex", "--yes"])
+        .assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr("Forgetting docker ps\nForgetting docker rm\n");
+
+    let mut cmd = setup_cmd(false);
+    let assert = cmd.args(["list"]).assert();
+    assert.success().stdout("git log -> A command\n");
+}
+
+#[test]
+fn doctor() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    // A missing store file is fine, and isn't created.
+    cmd(&["doctor"])
+        .success()
+        .stdout(predicate::str::contains("doesn't exist yet"));
+    assert!(!store_path.exists());
+
+    cmd(&["set", "foo", "A foo cli"]).success();
+    cmd(&["doctor"])
+        .success()
+        .stdout(predicate::str::contains("ok   Things in the store: 1"))
+        .stdout(predicate::str::contains("FAIL").not());
+
+    fs::write(&store_path, r#"{"foo": "A foo cli",}"#).unwrap();
+    cmd(&["doctor"])
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "FAIL The store file can't be loaded",
+        ));
+}
+
+#[test]
+fn several_stores() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let first = store_dir.path().join("first.json");
+    let second = store_dir.path().join("second.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.arg("-s")
+            .arg(&first)
+            .arg("-s")
+            .arg(&second)
+            .args(args)
+            .assert()
+    };
+
+    // Changes only go to the first store.
+    cmd(&["set", "foo", "A foo cli"]).success();
+    assert!(first.exists());
+    assert!(!second.exists());
+    let mut set_cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    set_cmd
+        .arg("-s")
+        .arg(&second)
+        .args(["set", "bar", "A bar cli"])
+        .assert()
+        .success();
+
+    cmd(&["list"]).success().stdout(format!(
+        "{}: bar -> A bar cli\n{}: foo -> A foo cli\n",
+        second.display(),
+        first.display()
+    ));
+    cmd(&["find", "bar cli"])
+        .success()
+        .stdout(predicate::str::starts_with(format!(
+            "{}: bar -> A bar cli\n",
+            second.display()
+        )));
+    cmd(&["find", "bar cli", "--first"])
+        .success()
+        .stdout("bar\n");
+}
+
+#[test]
+fn porcelain() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["set", "ls", "list files"]).success();
+    cmd(&["set", "printf", "print\ta\\tab\nthen a line"]).success();
+    cmd(&["alias", "ll", "ls"]).success();
+    cmd(&["list", "--porcelain", "--verbose"]).success().stdout(
+        "ll\tlist files\nls\tlist files\nprintf\tprint\\ta\\\\tab\\nthen a line\n",
+    );
+    cmd(&["find", "list files", "--porcelain", "--format", "{value}"])
+        .success()
+        .stdout("ll\tlist files\nls\tlist files\n");
+}
+
+#[test]
+fn find_combined() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["set", "git reset HEAD~", "undo the last commit"]).success();
+    cmd(&["find", "git undo"]).failure().code(2);
+    cmd(&["find", "git undo", "--combined"])
+        .success()
+        .stdout("git reset HEAD~ -> undo the last commit\n");
+    cmd(&["find", "git undo", "--combined", "--keys-only"])
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+#[cfg(unix)]
+fn edit_store() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let editor = store_dir.path().join("editor.sh");
+    let cmd = |script: &str| {
+        fs::write(&editor, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o700))
+            .unwrap();
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path)
+            .env_remove("VISUAL")
+            .env("EDITOR", &editor)
+            .arg("edit-store")
+            .assert()
+    };
+
+    cmd(r#"echo '{"foo": "A foo cli"}' > "$1""#)
+        .success()
+        .stderr("The store has 1 things.\n");
+    // Without a terminal, the editor isn't opened again.
+    cmd(r#"echo '{"foo": ' > "$1""#)
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("--repair"));
+    cmd("exit 3")
+        .failure()
+        .stderr(predicate::str::contains("exited with"));
+}
+
+#[test]
+#[cfg(unix)]
+fn find_run() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path)
+            .env("SHELL", "/bin/sh")
+            .args(args)
+            .assert()
+    };
+
+    cmd(&["set", "echo ran", "print something"]).success();
+    cmd(&["set", "exit 3", "print nothing"]).success();
+    // Without a terminal, the command can't be confirmed.
+    cmd(&["find", "print something", "--first", "--run"])
+        .failure()
+        .code(3)
+        .stdout("echo ran\n");
+    cmd(&["find", "print something", "--first", "--run", "--yes"])
+        .success()
+        .stdout("echo ran\nran\n");
+    cmd(&["find", "print", "--run", "--yes"])
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("since 2 things matched"));
+    cmd(&["find", "print nothing", "--first", "--run", "--yes"])
+        .failure()
+        .code(1);
+    cmd(&["find", "print something", "--first", "--run", "--dry-run"])
+        .success()
+        .stdout("echo ran\n");
+}
+
+#[test]
+fn categories() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["set", "ls", "list files", "--category", "shell"]).success();
+    cmd(&["set", "git ls-files", "list files", "--category", "git"]).success();
+    cmd(&["set", "dir", "list files"]).success();
+    cmd(&["find", "list files", "--category", "shell"])
+        .success()
+        .stdout("ls -> list files\n");
+    cmd(&["find", "list files", "--category", "docker"])
+        .failure()
+        .code(2);
+    cmd(&["categories"]).success().stdout("git: 1\nshell: 1\n");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn config_file() {
+    let config_dir = tempfile::tempdir().unwrap();
+    let store_path = config_dir.path().join("store.json");
+    let config_path = config_dir.path().join("wwt").join("config.toml");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("XDG_CONFIG_HOME", config_dir.path())
+            .env("WWT_STORE_PATH", &store_path)
+            .env_remove("WWT_FIND_LIMIT")
+            .args(args)
+            .assert()
+    };
+
+    cmd(&["set", "ls", "list files"]).success();
+    cmd(&["set", "exa", "list all files"]).success();
+    fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+    fs::write(
+        &config_path,
+        "default_limit = 1\ncase_mode = \"respect\"\nformat = \"{key}\"\n",
+    )
+    .unwrap();
+    cmd(&["find", "list files"]).success().stdout("ls\n");
+    cmd(&["find", "LIST"]).failure().code(2);
+    // The flags win over the config file.
+    cmd(&[
+        "find",
+        "LIST",
+        "--ignore-case",
+        "-n",
+        "2",
+        "--format",
+        "default",
+    ])
+    .success()
+    .stdout(predicate::str::contains("ls -> list files\n"))
+    .stdout(predicate::str::contains("exa -> list all files\n"));
+    cmd(&["list"]).success().stdout("exa\nls\n");
+
+    fs::write(&config_path, "default_limit = \"one\"\n").unwrap();
+    cmd(&["list"])
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("config.toml is invalid"));
+    // The doctor reports it instead of failing with it.
+    cmd(&["doctor"])
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains("FAIL The config file at"))
+        .stdout(predicate::str::contains("ok   Things in the store: 2"));
+}
+
+#[test]
+fn group_by_tag() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let store_path = store_dir.path().join("store.json");
+    let cmd = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("WWT_STORE_PATH", &store_path).args(args).assert()
+    };
+
+    cmd(&["set", "ls", "list files", "-t", "shell", "-t", "files"]).success();
+    cmd(&["set", "cat", "print files", "-t", "shell"]).success();
+    cmd(&["set", "dir", "list files on windows"]).success();
+    cmd(&["list", "--group-by-tag"]).success().stdout(
+        "files:\n    ls -> list files\nshell:\n    cat -> print files\n    \
+        ls -> list files\n(untagged):\n    dir -> list files on windows\n",
+    );
+    cmd(&["find", "list files", "--group-by-tag"])
+        .success()
+        .stdout(predicate::str::starts_with(
+            "files:\n    ls -> list files\n",
+        ))
+        .stdout(predicate::str::ends_with(
+            "(untagged):\n    dir -> list files on windows\n",
+        ));
+}